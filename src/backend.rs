@@ -0,0 +1,230 @@
+use nalgebra::DMatrix;
+use num::Complex;
+use rand::Rng;
+
+use circuit::gate::{Gate, Kind};
+use state::StateVector;
+use density::{DensityMatrix, to_density};
+use stabilizer::Tableau;
+use metrics::TOLERANCE;
+
+/// Simulator that circuits can run on, so code driving a circuit doesn't need
+/// to care how the state is stored.
+///
+/// Qubits are ordered like elsewhere in the crate, qubit 0 being the most
+/// significant bit of a basis state's index.
+pub trait Backend {
+    /// Register of `qubits` qubits in |0...0⟩.
+    fn new(qubits: usize) -> Self where Self: Sized;
+    fn qubits(&self) -> usize;
+    /// Applies `gate` to `qubits`, one per port in port order. Returns `false`
+    /// without changing the state when the backend can't simulate the gate.
+    fn apply_gate(&mut self, gate: &Gate, qubits: &[usize]) -> bool;
+    /// Puts `qubit`, which is still |0⟩, into the state that the gate without
+    /// inputs `gate` emits at output `port`. Returns `false` like `apply_gate`.
+    fn prepare(&mut self, gate: &Gate, port: u32, qubit: usize) -> bool;
+    /// Measures `qubit` in the computational basis, collapsing the state.
+    fn measure<R: Rng>(&mut self, qubit: usize, rng: &mut R) -> usize where Self: Sized;
+    /// Probabilities of the basis states, ordered like `basis_n` yields them.
+    fn probabilities(&self) -> Vec<f64>;
+    /// Amplitudes of the basis states up to global phase, or `None` when the
+    /// state is mixed.
+    fn amplitudes(&self) -> Option<Vec<Complex<f64>>>;
+    /// Density matrix of the state, which works for mixed states too. Like
+    /// `probabilities` this is exponential in the number of qubits.
+    fn density(&self) -> DMatrix<Complex<f64>>;
+}
+
+/// Unitary taking |0⟩ to the normalized single qubit state `state`.
+fn preparation(state: &DMatrix<Complex<f64>>) -> DMatrix<Complex<f64>> {
+    let norm = (state[(0, 0)].norm_sqr() + state[(1, 0)].norm_sqr()).sqrt();
+    let (a, b) = (state[(0, 0)] / norm, state[(1, 0)] / norm);
+    DMatrix::from_column_vector(2, 2, &[a, b, -b.conj(), a.conj()])
+}
+
+fn emitted(gate: &Gate, port: u32) -> Option<DMatrix<Complex<f64>>> {
+    gate.process(&[]).into_iter().nth(port as usize).map(|s| preparation(&s))
+}
+
+impl Backend for StateVector {
+    fn new(qubits: usize) -> StateVector {
+        StateVector::new(qubits)
+    }
+
+    fn qubits(&self) -> usize {
+        self.qubits()
+    }
+
+    fn apply_gate(&mut self, gate: &Gate, qubits: &[usize]) -> bool {
+        match gate.matrix() {
+            Some(m) => {
+                self.apply(&m, qubits);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn prepare(&mut self, gate: &Gate, port: u32, qubit: usize) -> bool {
+        match emitted(gate, port) {
+            Some(m) => {
+                self.apply(&m, &[qubit]);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn measure<R: Rng>(&mut self, qubit: usize, rng: &mut R) -> usize {
+        self.measure(qubit, rng)
+    }
+
+    fn probabilities(&self) -> Vec<f64> {
+        self.probabilities()
+    }
+
+    fn amplitudes(&self) -> Option<Vec<Complex<f64>>> {
+        Some(self.amplitudes().to_vec())
+    }
+
+    fn density(&self) -> DMatrix<Complex<f64>> {
+        to_density(&self.mat())
+    }
+}
+
+impl Backend for DensityMatrix {
+    fn new(qubits: usize) -> DensityMatrix {
+        DensityMatrix::new(qubits)
+    }
+
+    fn qubits(&self) -> usize {
+        self.qubits()
+    }
+
+    fn apply_gate(&mut self, gate: &Gate, qubits: &[usize]) -> bool {
+        match gate.matrix() {
+            Some(m) => {
+                self.apply(&m, qubits);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn prepare(&mut self, gate: &Gate, port: u32, qubit: usize) -> bool {
+        match emitted(gate, port) {
+            Some(m) => {
+                self.apply(&m, &[qubit]);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn measure<R: Rng>(&mut self, qubit: usize, rng: &mut R) -> usize {
+        self.measure(qubit, rng)
+    }
+
+    fn probabilities(&self) -> Vec<f64> {
+        self.probabilities()
+    }
+
+    fn amplitudes(&self) -> Option<Vec<Complex<f64>>> {
+        if (self.purity() - 1.).abs() > TOLERANCE {
+            return None;
+        }
+        // A pure state is proportional to any nonzero column of its density matrix.
+        let dim = 1 << self.qubits();
+        let col = (0..dim).fold(0, |best, i| if self[(i, i)].re > self[(best, best)].re { i } else { best });
+        let norm = self[(col, col)].re.sqrt();
+        Some((0..dim).map(|r| self[(r, col)] / norm).collect())
+    }
+
+    fn density(&self) -> DMatrix<Complex<f64>> {
+        self.mat()
+    }
+}
+
+impl Backend for Tableau {
+    fn new(qubits: usize) -> Tableau {
+        Tableau::new(qubits)
+    }
+
+    fn qubits(&self) -> usize {
+        self.qubits()
+    }
+
+    fn apply_gate(&mut self, gate: &Gate, qubits: &[usize]) -> bool {
+        match gate.kind() {
+            Kind::Input { .. } => false,
+            kind => self.apply(&kind, qubits),
+        }
+    }
+
+    fn prepare(&mut self, gate: &Gate, port: u32, qubit: usize) -> bool {
+        match gate.kind() {
+            kind @ Kind::Input { .. } => port == 0 && self.apply(&kind, &[qubit]),
+            _ => false,
+        }
+    }
+
+    fn measure<R: Rng>(&mut self, qubit: usize, rng: &mut R) -> usize {
+        self.measure(qubit, rng)
+    }
+
+    fn probabilities(&self) -> Vec<f64> {
+        self.probabilities()
+    }
+
+    fn amplitudes(&self) -> Option<Vec<Complex<f64>>> {
+        Some(self.amplitudes())
+    }
+
+    fn density(&self) -> DMatrix<Complex<f64>> {
+        let amplitudes = self.amplitudes();
+        to_density(&DMatrix::from_column_vector(amplitudes.len(), 1, &amplitudes))
+    }
+}
+
+#[test]
+fn backends_agree_test() {
+    use circuit::gate::{Input, Hadamard, ControlNot, S, T};
+    use std::f64::consts::PI;
+    fn run<B: Backend>(gates: &[(Box<Gate>, Vec<usize>)]) -> B {
+        let mut state = B::new(3);
+        assert!(state.prepare(&*Input::with_angles(PI / 2., PI / 2.), 0, 1));
+        for &(ref gate, ref qubits) in gates {
+            assert!(state.apply_gate(&**gate, qubits));
+        }
+        state
+    }
+    let gates = vec![
+        (Hadamard::new(), vec![0]),
+        (ControlNot::new(), vec![0, 2]),
+        (S::new(), vec![2]),
+        (ControlNot::new(), vec![1, 0]),
+    ];
+    let vector = run::<StateVector>(&gates);
+    let density = run::<DensityMatrix>(&gates);
+    let tableau = run::<Tableau>(&gates);
+    let close = |a: &[f64], b: &[f64]| a.iter().zip(b).all(|(a, b)| (a - b).abs() < 0.000001);
+    assert!(close(&vector.probabilities(), &Backend::probabilities(&density)));
+    assert!(close(&vector.probabilities(), &Backend::probabilities(&tableau)));
+    let reference = Backend::amplitudes(&vector).unwrap();
+    for amplitudes in vec![Backend::amplitudes(&density), Backend::amplitudes(&tableau)] {
+        let overlap = amplitudes.unwrap().iter().zip(&reference)
+            .fold(Complex::new(0., 0.), |acc, (a, b)| acc + a.conj() * b);
+        assert!((overlap.norm() - 1.).abs() < 0.000001);
+    }
+    let reference = Backend::density(&vector);
+    for rho in vec![Backend::density(&density), Backend::density(&tableau)] {
+        assert!((0..8).all(|r| (0..8).all(|c| (rho[(r, c)] - reference[(r, c)]).norm() < 0.000001)));
+    }
+
+    let mut tableau = Tableau::new(1);
+    assert!(!tableau.apply_gate(&*T::new(), &[0]));
+    assert!(!tableau.prepare(&*Input::with_angles(PI / 4., 0.), 0, 0));
+    let mut density = DensityMatrix::new(1);
+    density.apply_channel(&::noise::depolarizing(0.5), &[0]);
+    assert_eq!(None, Backend::amplitudes(&density));
+}
@@ -0,0 +1,102 @@
+use nalgebra::DMatrix;
+use num::Complex;
+
+use std::f64::consts::PI;
+
+use density::to_density;
+use Qubit;
+
+/// Point in the Bloch ball. Pure states lie on the unit sphere and mixed states inside it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Bloch {
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+}
+
+impl Bloch {
+    pub fn new(x: f64, y: f64, z: f64) -> Bloch {
+        Bloch {
+            x: x,
+            y: y,
+            z: z,
+        }
+    }
+
+    /// Point on the sphere for the angles `gate::Input` prepares its state from.
+    pub fn from_angles(theta: f64, phi: f64) -> Bloch {
+        Bloch::new(theta.sin() * phi.cos(), theta.sin() * phi.sin(), theta.cos())
+    }
+
+    pub fn from_qubit(qubit: &Qubit) -> Bloch {
+        Bloch::from_mat(qubit.mat())
+    }
+
+    /// Takes a single-qubit state vector or density matrix, such as one from `partial_trace`.
+    pub fn from_mat(state: &DMatrix<Complex<f64>>) -> Bloch {
+        assert_eq!(2, state.nrows());
+        let rho = to_density(state);
+        Bloch::new(2. * rho[(0, 1)].re, 2. * rho[(1, 0)].im, rho[(0, 0)].re - rho[(1, 1)].re)
+    }
+
+    /// Distance from the center, 1 for pure states and 0 for the maximally mixed state.
+    pub fn length(&self) -> f64 {
+        (self.x * self.x + self.y * self.y + self.z * self.z).sqrt()
+    }
+
+    /// Polar angle theta in [0, π] and azimuth phi in [0, 2π), matching `gate::Input`.
+    ///
+    /// Mixed states get the angles of the direction they point to. Where an
+    /// angle is undefined, like phi at the poles, it is 0.
+    pub fn angles(&self) -> (f64, f64) {
+        let length = self.length();
+        if length < 1e-12 {
+            return (0., 0.);
+        }
+        let theta = (self.z / length).max(-1.).min(1.).acos();
+        let mut phi = self.y.atan2(self.x);
+        if self.x.abs() < 1e-12 && self.y.abs() < 1e-12 {
+            phi = 0.;
+        }
+        if phi < 0. {
+            phi += 2. * PI;
+        }
+        (theta, phi)
+    }
+}
+
+#[test]
+fn bloch_test() {
+    use num::{One, Zero};
+    use num::Complex as C;
+    let y = 1. / 2f64.sqrt();
+    let close = |a: Bloch, b: Bloch| (a.x - b.x).abs() < 0.000001 && (a.y - b.y).abs() < 0.000001 && (a.z - b.z).abs() < 0.000001;
+    assert!(close(Bloch::new(0., 0., 1.), Bloch::from_qubit(&Qubit::one())));
+    assert!(close(Bloch::new(0., 0., -1.), Bloch::from_qubit(&Qubit::new(C::zero(), C::one()))));
+    assert!(close(Bloch::new(1., 0., 0.), Bloch::from_qubit(&Qubit::new(C::new(y, 0.), C::new(y, 0.)))));
+    assert!(close(Bloch::new(0., 1., 0.), Bloch::from_qubit(&Qubit::new(C::new(y, 0.), C::new(0., y)))));
+    assert!(close(Bloch::new(0., -1., 0.), Bloch::from_qubit(&Qubit::new(C::new(y, 0.), C::new(0., -y)))));
+
+    let mixed = DMatrix::from_column_vector(2, 2, &[C::new(0.75, 0.), C::zero(), C::zero(), C::new(0.25, 0.)]);
+    let b = Bloch::from_mat(&mixed);
+    assert!(close(Bloch::new(0., 0., 0.5), b));
+    assert!((b.length() - 0.5).abs() < 0.000001);
+    assert_eq!((0., 0.), b.angles());
+}
+
+#[test]
+fn input_angles_test() {
+    use circuit::gate::{Gate, Input};
+    for &(theta, phi) in &[(0.3, 0.), (1.2, 2.5), (PI / 2., 3. * PI / 2.), (2.9, 6.)] {
+        let state = Input::with_angles(theta, phi).process(&[]).remove(0);
+        let b = Bloch::from_mat(&state);
+        assert!(close_angles(b.angles(), (theta, phi)));
+        assert!(close_angles(Bloch::from_angles(theta, phi).angles(), (theta, phi)));
+    }
+    assert_eq!((PI, 0.), Bloch::new(0., 0., -1.).angles());
+}
+
+#[cfg(test)]
+fn close_angles(a: (f64, f64), b: (f64, f64)) -> bool {
+    (a.0 - b.0).abs() < 0.000001 && (a.1 - b.1).abs() < 0.000001
+}
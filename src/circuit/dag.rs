@@ -1,13 +1,17 @@
 use daggy::{PetGraph, Dag, Walker, NodeIndex, EdgeIndex, WouldCycle};
 use daggy::petgraph::graph::IndexType;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+use serde::{Serialize, Serializer, Deserialize, Deserializer};
+
+use std::collections::VecDeque;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Edge {
     pub source: u32,
     pub target: u32,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct Port<Ix: IndexType> {
     pub node: NodeIndex<Ix>,
     pub port: u32,
@@ -17,6 +21,20 @@ pub fn port<Ix: IndexType>(node: NodeIndex<Ix>, port: u32) -> Port<Ix> {
     Port{node: node, port: port}
 }
 
+/// Written as a `(node, port)` pair.
+impl<Ix: IndexType> Serialize for Port<Ix> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        (self.node.index(), self.port).serialize(serializer)
+    }
+}
+
+impl<'de, Ix: IndexType> Deserialize<'de> for Port<Ix> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Port<Ix>, D::Error> {
+        let (node, p) = <(usize, u32)>::deserialize(deserializer)?;
+        Ok(port(NodeIndex::new(node), p))
+    }
+}
+
 pub struct PortNumbered<N, Ix: IndexType = u32> {
     dag: Dag<N, Edge, Ix>,
 }
@@ -40,12 +58,41 @@ impl<N, Ix: IndexType> PortNumbered<N, Ix> {
         Children(&self.dag, self.dag.children(node))
     }
 
+    /// Every node ordered so that each one comes after all of its parents.
+    ///
+    /// Ties are broken by node index, so the order is stable for a given graph.
+    pub fn topological_order(&self) -> Vec<NodeIndex<Ix>> {
+        let count = self.dag.node_count();
+        let mut incoming = vec![0; count];
+        let mut children = vec![vec![]; count];
+        for e in self.dag.raw_edges() {
+            incoming[e.target().index()] += 1;
+            children[e.source().index()].push(e.target().index());
+        }
+        let mut ready = (0..count).filter(|&n| incoming[n] == 0).collect::<VecDeque<_>>();
+        let mut result = Vec::with_capacity(count);
+        while let Some(n) = ready.pop_front() {
+            result.push(NodeIndex::new(n));
+            for &c in &children[n] {
+                incoming[c] -= 1;
+                if incoming[c] == 0 {
+                    ready.push_back(c);
+                }
+            }
+        }
+        result
+    }
+
     pub fn update_edge(&mut self, src: Port<Ix>, trg: Port<Ix>) -> Result<EdgeIndex<Ix>, WouldBreak> {
         let replaced = self.dag.parents(trg.node).find_edge(&self.dag, |dag, e, _| dag.edge_weight(e).unwrap().target == trg.port);
-        let result = self.dag.update_edge(src.node, trg.node, Edge{source: src.port, target: trg.port}).map_err(Into::into);
+        // Nodes can share several edges on different ports, so this can't use
+        // `Dag::update_edge` which would merge them into one.
+        let result = self.dag.add_edge(src.node, trg.node, Edge{source: src.port, target: trg.port}).map_err(Into::into);
         if let Ok(_) = result {
             if let Some(e) = replaced {
                 self.dag.remove_edge(e);
+                // Removing an edge moves the last one, which is the new edge, into its place.
+                return Ok(e);
             }
         }
         result
@@ -101,6 +148,22 @@ impl<N, Ix: IndexType> PortNumbered<N, Ix> {
     pub fn graph(&self) -> &PetGraph<N, Edge, Ix> {
         self.dag.graph()
     }
+
+    /// Graphviz DOT graph with nodes named by index and labelled by `label`,
+    /// and each edge labelled `source_port -> target_port`.
+    pub fn to_dot<F: Fn(&N) -> String>(&self, label: F) -> String {
+        let escape = |s: String| s.replace('\\', "\\\\").replace('"', "\\\"");
+        let mut result = "digraph {\n    rankdir=LR;\n".to_string();
+        for (i, n) in self.dag.raw_nodes().iter().enumerate() {
+            result.push_str(&format!("    {} [label=\"{}\"];\n", i, escape(label(&n.weight))));
+        }
+        for e in self.dag.raw_edges() {
+            result.push_str(&format!("    {} -> {} [label=\"{} -> {}\"];\n",
+                e.source().index(), e.target().index(), e.weight.source, e.weight.target));
+        }
+        result.push_str("}\n");
+        result
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -157,3 +220,33 @@ impl<'a, N: 'a, Ix: IndexType> Iterator for Children<'a, N, Ix> {
         }
     }
 }
+
+#[test]
+fn update_edge_test() {
+    let mut dag = PortNumbered::<()>::new();
+    let a = dag.add_node(());
+    let b = dag.add_node(());
+    let c = dag.add_node(());
+    // Edges between the same nodes on different ports are kept apart.
+    let first = dag.update_edge(port(a, 0), port(b, 0)).unwrap();
+    let second = dag.update_edge(port(a, 1), port(b, 1)).unwrap();
+    assert!(first != second);
+    assert_eq!(vec![(port(a, 0), port(b, 0)), (port(a, 1), port(b, 1))], dag.edges().collect::<Vec<_>>());
+
+    // A new edge into a taken port replaces the old one, even from the same parent.
+    assert_eq!(Ok(first), dag.update_edge(port(a, 2), port(b, 0)));
+    assert_eq!(2, dag.edge_count());
+    let mut parents = dag.parents(b).collect::<Vec<_>>();
+    parents.sort_by_key(|p| p.1);
+    assert_eq!(vec![(port(a, 2), 0), (port(a, 1), 1)], parents);
+    assert_eq!(Ok(second), dag.update_edge(port(c, 0), port(b, 1)));
+    assert_eq!(vec![(port(a, 2), port(b, 0)), (port(c, 0), port(b, 1))], dag.edges().collect::<Vec<_>>());
+
+    // An edge that would close a cycle leaves the old one in place.
+    let d = dag.add_node(());
+    dag.update_edge(port(d, 0), port(c, 0)).unwrap();
+    assert_eq!(Err(WouldBreak::WouldCycle), dag.update_edge(port(b, 0), port(c, 0)).map(|_| ()));
+    assert_eq!(vec![(port(d, 0), 0)], dag.parents(c).collect::<Vec<_>>());
+    assert_eq!(Some(port(a, 2)), dag.remove_edge_to_port(port(b, 0)));
+    assert_eq!(None, dag.remove_edge_to_port(port(b, 0)));
+}
@@ -0,0 +1,64 @@
+use super::Game;
+use super::gate::Kind;
+
+impl<T> Game<T> {
+    /// Graphviz DOT graph of the wiring, with each node labelled by its gate
+    /// and each edge by the ports it connects. Render it with `dot -Tsvg`.
+    pub fn to_dot(&self) -> String {
+        self.dag.to_dot(|n| name(&n.process.kind()))
+    }
+}
+
+/// Name of the gate with its parameters, leaving out the matrix of
+/// `Controlled`.
+fn name(kind: &Kind) -> String {
+    match *kind {
+        Kind::Input { theta, phi } if theta == 0. && phi == 0. => "Input".to_string(),
+        Kind::Input { theta, phi } => format!("Input({}, {})", theta, phi),
+        Kind::Rx(theta) => format!("Rx({})", theta),
+        Kind::Ry(theta) => format!("Ry({})", theta),
+        Kind::Rz(theta) => format!("Rz({})", theta),
+        Kind::Phase(phi) => format!("Phase({})", phi),
+        Kind::U3(theta, phi, lambda) => format!("U3({}, {}, {})", theta, phi, lambda),
+        Kind::Controlled { controls, ref negative, .. } if negative.is_empty() => format!("Controlled({})", controls),
+        Kind::Controlled { controls, ref negative, .. } => format!("Controlled({}, negative {:?})", controls, negative),
+        ref kind => format!("{:?}", kind),
+    }
+}
+
+#[test]
+fn to_dot_test() {
+    use super::port;
+    use super::gate::{Input, Output, Hadamard, ControlNot, Controlled, Rz};
+    let mut game = Game::new();
+    let a = game.add(Input::new(), ());
+    let b = game.add(Input::with_angles(0.5, 0.), ());
+    let h = game.add(Hadamard::new(), ());
+    let cnot = game.add(ControlNot::new(), ());
+    let rz = game.add(Rz::new(0.25), ());
+    let controlled = game.add(Controlled::new(1, vec![0], ::not()), ());
+    let out = game.add(Output::new(), ());
+    game.connect(port(a, 0), port(h, 0));
+    game.connect(port(h, 0), port(cnot, 1));
+    game.connect(port(b, 0), port(cnot, 0));
+    game.connect(port(cnot, 1), port(rz, 0));
+    game.connect(port(rz, 0), port(controlled, 0));
+    game.connect(port(cnot, 0), port(controlled, 1));
+    game.connect(port(controlled, 1), port(out, 0));
+    assert_eq!("digraph {\n    rankdir=LR;\n\
+        \x20   0 [label=\"Input\"];\n\
+        \x20   1 [label=\"Input(0.5, 0)\"];\n\
+        \x20   2 [label=\"Hadamard\"];\n\
+        \x20   3 [label=\"ControlNot\"];\n\
+        \x20   4 [label=\"Rz(0.25)\"];\n\
+        \x20   5 [label=\"Controlled(1, negative [0])\"];\n\
+        \x20   6 [label=\"Output\"];\n\
+        \x20   0 -> 2 [label=\"0 -> 0\"];\n\
+        \x20   2 -> 3 [label=\"0 -> 1\"];\n\
+        \x20   1 -> 3 [label=\"0 -> 0\"];\n\
+        \x20   3 -> 4 [label=\"1 -> 0\"];\n\
+        \x20   4 -> 5 [label=\"0 -> 0\"];\n\
+        \x20   3 -> 5 [label=\"0 -> 1\"];\n\
+        \x20   5 -> 6 [label=\"1 -> 0\"];\n\
+        }\n", game.to_dot());
+}
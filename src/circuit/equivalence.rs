@@ -0,0 +1,96 @@
+use nalgebra::DMatrix;
+use num::Complex;
+
+use super::{Game, EvalError};
+use metrics::TOLERANCE;
+
+/// Result of comparing the operation a circuit implements with another one.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Equivalence {
+    /// Both do the same up to global phase.
+    Equal,
+    /// Both take this input state, laid out like the columns of
+    /// `Schedule::unitary`, to states that differ by more than global phase.
+    Differ(DMatrix<Complex<f64>>),
+    /// The operations act on this many and that many qubits.
+    QubitsDiffer(usize, usize),
+}
+
+impl<T> Game<T> {
+    /// Checks whether this circuit does the same as `other`, up to global phase.
+    ///
+    /// Inputs and outputs of the two circuits are matched by the order of
+    /// their node indices, like in `unitary`.
+    pub fn equivalent<U>(&self, other: &Game<U>) -> Result<Equivalence, EvalError> {
+        Ok(compare(&self.unitary()?, &other.unitary()?))
+    }
+
+    /// Checks whether this circuit implements `unitary` up to global phase,
+    /// with the qubit ordering described in `Schedule::unitary`.
+    pub fn implements(&self, unitary: &DMatrix<Complex<f64>>) -> Result<Equivalence, EvalError> {
+        Ok(compare(&self.unitary()?, unitary))
+    }
+}
+
+fn compare(a: &DMatrix<Complex<f64>>, b: &DMatrix<Complex<f64>>) -> Equivalence {
+    if a.nrows() != b.nrows() || a.ncols() != b.ncols() {
+        return Equivalence::QubitsDiffer(a.ncols().trailing_zeros() as usize, b.ncols().trailing_zeros() as usize);
+    }
+    let dim = a.ncols();
+    let basis = |i: usize| {
+        let mut state = DMatrix::new_zeros(dim, 1);
+        state[(i, 0)] = Complex::new(1., 0.);
+        state
+    };
+    // The operations agree up to global phase exactly when every basis state
+    // is mapped to the same state with one common phase.
+    let mut phases = Vec::with_capacity(dim);
+    for c in 0..dim {
+        let overlap = (0..a.nrows()).fold(Complex::new(0., 0.), |acc, r| acc + b[(r, c)].conj() * a[(r, c)]);
+        if (overlap.norm() - 1.).abs() > TOLERANCE {
+            return Equivalence::Differ(basis(c));
+        }
+        phases.push(overlap);
+    }
+    match (1..dim).find(|&c| (phases[c] - phases[0]).norm() > TOLERANCE) {
+        // A superposition of two basis states picks up the relative phase.
+        Some(c) => {
+            let mut state = basis(0);
+            state[(c, 0)] = Complex::new(1., 0.);
+            Equivalence::Differ(state * Complex::new(1. / 2f64.sqrt(), 0.))
+        }
+        None => Equivalence::Equal,
+    }
+}
+
+#[test]
+fn equivalence_test() {
+    use super::{build, line};
+    use super::gate::{Hadamard, Not, PauliZ, S, Rz, ControlNot};
+    use {hadamard, not, pauli_z, apply_to_qubit, control_not};
+    let hzh = line(vec![Hadamard::new(), PauliZ::new(), Hadamard::new()]);
+    assert_eq!(Ok(Equivalence::Equal), hzh.equivalent(&line(vec![Not::new()])));
+    assert_eq!(Ok(Equivalence::Equal), hzh.implements(&(not() * Complex::new(0., 1.))));
+    // Rz(π) is Z up to a global phase of -i.
+    assert_eq!(Ok(Equivalence::Equal), line(vec![Rz::new(::std::f64::consts::PI)]).implements(&pauli_z()));
+
+    let y = 1. / 2f64.sqrt();
+    let expected = DMatrix::from_column_vector(2, 1, &[Complex::new(y, 0.), Complex::new(y, 0.)]);
+    assert_eq!(Ok(Equivalence::Differ(expected)), line(vec![S::new()]).implements(&pauli_z()));
+    let expected = DMatrix::from_column_vector(2, 1, &[Complex::new(1., 0.), Complex::new(0., 0.)]);
+    assert_eq!(Ok(Equivalence::Differ(expected)), hzh.implements(&hadamard()));
+
+    let cnot = build(2, vec![(ControlNot::new(), vec![0, 1])]);
+    assert_eq!(Ok(Equivalence::Equal), cnot.implements(&control_not(0, 1, 2)));
+    match cnot.implements(&control_not(1, 0, 2)) {
+        Ok(Equivalence::Differ(state)) => {
+            let u = control_not(0, 1, 2) * state.clone();
+            let v = control_not(1, 0, 2) * state;
+            let overlap = (0..4).fold(Complex::new(0., 0.), |acc, i| acc + v[(i, 0)].conj() * u[(i, 0)]);
+            assert!(overlap.norm() < 0.999);
+        }
+        r => panic!("{:?}", r),
+    }
+    assert_eq!(Ok(Equivalence::QubitsDiffer(2, 1)), cnot.equivalent(&hzh));
+    assert_eq!(Ok(Equivalence::QubitsDiffer(1, 2)), hzh.implements(&apply_to_qubit(hadamard(), 0, 2)));
+}
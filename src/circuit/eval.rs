@@ -0,0 +1,399 @@
+use nalgebra::DMatrix;
+use num::Complex;
+use daggy::NodeIndex;
+use rand::Rng;
+
+use std::collections::HashMap;
+
+use super::{Game, Port, port};
+use state::StateVector;
+use measure::outcome_of;
+use density::partial_trace;
+use stabilizer::Tableau;
+use backend::Backend;
+use kronecker_product;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EvalError {
+    /// Nothing is connected to this input port.
+    Unconnected(Port<u32>),
+    /// This output port is connected to more than one input, which would clone its qubit.
+    FanOut(Port<u32>),
+    /// The gate at this node doesn't have as many outputs as it has qubits.
+    WrongOutputCount(NodeIndex),
+    /// The gate at this node doesn't provide a matrix to apply to the register.
+    NoMatrix(NodeIndex),
+    /// This output port ends its wire without reaching an `Output`.
+    Unterminated(Port<u32>),
+    /// The backend can't simulate the gate at this node.
+    Unsupported(NodeIndex),
+    /// An edge uses this port, which its gate doesn't have.
+    NoPort(Port<u32>),
+}
+
+/// Circuit flattened into operations on a joint register with one qubit per wire.
+///
+/// Every output port of a gate without inputs, such as `Input`, starts a wire.
+/// Wires are numbered in the order of those gates' node indices, and a wire
+/// keeps its qubit through every gate it passes.
+pub struct Schedule {
+    inputs: Vec<(NodeIndex, u32, DMatrix<Complex<f64>>)>,
+    ops: Vec<(NodeIndex, DMatrix<Complex<f64>>, Vec<usize>)>,
+    outputs: Vec<(NodeIndex, usize)>,
+    unterminated: Vec<Port<u32>>,
+}
+
+impl Schedule {
+    /// Number of wires in the register.
+    pub fn qubits(&self) -> usize {
+        self.inputs.len()
+    }
+
+    /// Output port that starts each wire, indexed by qubit.
+    pub fn inputs(&self) -> Vec<Port<u32>> {
+        self.inputs.iter().map(|i| port(i.0, i.1)).collect()
+    }
+
+    /// Qubit arriving at each input port of every gate without outputs, such as `Output`.
+    pub fn outputs(&self) -> &[(NodeIndex, usize)] {
+        &self.outputs
+    }
+
+    /// Applies the gates of the circuit to `state`, which has one qubit per wire.
+    pub fn apply(&self, state: &mut StateVector) {
+        for &(_, ref gate, ref qubits) in &self.ops {
+            state.apply(gate, qubits);
+        }
+    }
+
+    /// Overall unitary of the circuit, leaving out the states the inputs prepare.
+    ///
+    /// Columns are indexed by wire, qubit 0 being the wire of the lowest
+    /// numbered input node. Rows are indexed by output, qubit 0 being the one
+    /// arriving at the lowest numbered output node. Both follow `basis_n`.
+    pub fn unitary(&self) -> Result<DMatrix<Complex<f64>>, EvalError> {
+        if let Some(&p) = self.unterminated.first() {
+            return Err(EvalError::Unterminated(p));
+        }
+        let mut outputs = self.outputs.clone();
+        outputs.sort_by_key(|o| o.0);
+        let order = outputs.into_iter().map(|o| o.1).collect::<Vec<_>>();
+        let qubits = self.qubits();
+        let dim = 1 << qubits;
+        let mut result = DMatrix::new_zeros(dim, dim);
+        for col in 0..dim {
+            let mut state = vec![Complex::new(0., 0.); dim];
+            state[col] = Complex::new(1., 0.);
+            let mut state = StateVector::from_amplitudes(state);
+            self.apply(&mut state);
+            for (i, a) in state.amplitudes().iter().enumerate() {
+                result[(outcome_of(i, qubits, &order), col)] = *a;
+            }
+        }
+        Ok(result)
+    }
+
+    /// Joint state of the wires as prepared by the gates that start them.
+    pub fn initial_state(&self) -> StateVector {
+        let states = self.inputs.iter().map(|i| i.2.clone()).collect::<Vec<_>>();
+        StateVector::from_mat(&kronecker_product(&states))
+    }
+}
+
+/// Final joint state of a circuit.
+pub struct Evaluation<B = StateVector> {
+    pub state: B,
+    /// Qubit of `state` arriving at each `Output`, in topological order.
+    pub outputs: Vec<(NodeIndex, usize)>,
+}
+
+impl<B> Evaluation<B> {
+    /// Qubit that ended up at `node`.
+    pub fn qubit(&self, node: NodeIndex) -> Option<usize> {
+        self.outputs.iter().find(|o| o.0 == node).map(|o| o.1)
+    }
+}
+
+impl<B: Backend> Evaluation<B> {
+    /// Measures the qubit arriving at every `Output`, in the order of `outputs`.
+    pub fn measure<R: Rng>(&mut self, rng: &mut R) -> Vec<(NodeIndex, usize)> {
+        let state = &mut self.state;
+        self.outputs.iter().map(|&(node, q)| (node, state.measure(q, rng))).collect()
+    }
+}
+
+impl Evaluation<StateVector> {
+    /// Reduced density matrix of the qubit that ended up at `node`.
+    pub fn output_state(&self, node: NodeIndex) -> Option<DMatrix<Complex<f64>>> {
+        self.qubit(node).map(|q| partial_trace(&self.state.mat(), &[q]))
+    }
+}
+
+impl<T> Game<T> {
+    /// Maps every wire of the circuit to a qubit of a joint register.
+    pub fn schedule(&self) -> Result<Schedule, EvalError> {
+        if let Some(p) = self.missing_port() {
+            return Err(EvalError::NoPort(p));
+        }
+        let mut wires = HashMap::new();
+        let mut schedule = Schedule {
+            inputs: vec![],
+            ops: vec![],
+            outputs: vec![],
+            unterminated: vec![],
+        };
+        for node in self.dag.topological_order() {
+            let gate = &self.dag.node_weight(node).unwrap().process;
+            if gate.max_in() == 0 {
+                let states = gate.process(&[]);
+                if states.len() != gate.max_out() as usize {
+                    return Err(EvalError::WrongOutputCount(node));
+                }
+                for (i, state) in states.into_iter().enumerate() {
+                    wires.insert(port(node, i as u32), schedule.inputs.len());
+                    schedule.inputs.push((node, i as u32, state));
+                }
+                continue;
+            }
+            let mut sources = vec![None; gate.max_in() as usize];
+            for (src, trg) in self.dag.parents(node) {
+                sources[trg as usize] = Some(src);
+            }
+            let mut qubits = Vec::with_capacity(sources.len());
+            for (i, src) in sources.into_iter().enumerate() {
+                let src = match src {
+                    Some(src) => src,
+                    None => return Err(EvalError::Unconnected(port(node, i as u32))),
+                };
+                match wires.remove(&src) {
+                    Some(q) => qubits.push(q),
+                    None => return Err(EvalError::FanOut(src)),
+                }
+            }
+            if gate.max_out() == 0 {
+                schedule.outputs.extend(qubits.into_iter().map(|q| (node, q)));
+                continue;
+            }
+            if gate.max_out() != gate.max_in() {
+                return Err(EvalError::WrongOutputCount(node));
+            }
+            let matrix = match gate.matrix() {
+                Some(m) => m,
+                None => return Err(EvalError::NoMatrix(node)),
+            };
+            for (i, &q) in qubits.iter().enumerate() {
+                wires.insert(port(node, i as u32), q);
+            }
+            schedule.ops.push((node, matrix, qubits));
+        }
+        schedule.unterminated = wires.into_iter().map(|w| w.0).collect();
+        schedule.unterminated.sort_by_key(|p| (p.node, p.port));
+        Ok(schedule)
+    }
+
+    /// Overall unitary of the circuit between its inputs and outputs, with the
+    /// qubit ordering described in `Schedule::unitary`.
+    ///
+    /// Keep the result around when it's needed repeatedly; this walks the graph
+    /// and applies the circuit once per basis state.
+    pub fn unitary(&self) -> Result<DMatrix<Complex<f64>>, EvalError> {
+        self.schedule()?.unitary()
+    }
+
+    /// Runs the circuit on a joint register, so gates on entangled wires act on
+    /// the whole state rather than on a copy of their own wire.
+    pub fn evaluate(&self) -> Result<Evaluation, EvalError> {
+        self.simulate()
+    }
+
+    /// Like `evaluate`, but on any backend. Gates the backend can't simulate
+    /// give `EvalError::Unsupported`.
+    pub fn simulate<B: Backend>(&self) -> Result<Evaluation<B>, EvalError> {
+        let schedule = self.schedule()?;
+        let mut state = B::new(schedule.qubits());
+        for (q, &(node, port, _)) in schedule.inputs.iter().enumerate() {
+            if !state.prepare(&*self.dag.node_weight(node).unwrap().process, port, q) {
+                return Err(EvalError::Unsupported(node));
+            }
+        }
+        for &(node, _, ref qubits) in &schedule.ops {
+            if !state.apply_gate(&*self.dag.node_weight(node).unwrap().process, qubits) {
+                return Err(EvalError::Unsupported(node));
+            }
+        }
+        Ok(Evaluation {
+            state: state,
+            outputs: schedule.outputs,
+        })
+    }
+
+    /// Runs the circuit once and measures the qubit arriving at every `Output`,
+    /// returning the outcomes in the order of `Evaluation::outputs`.
+    ///
+    /// Circuits made only of Clifford gates, with inputs preparing stabilizer
+    /// states, run on a `Tableau` and can have hundreds of qubits. Others run
+    /// on a state vector.
+    pub fn measure<R: Rng>(&self, rng: &mut R) -> Result<Vec<(NodeIndex, usize)>, EvalError> {
+        match self.simulate::<Tableau>() {
+            Ok(mut evaluation) => Ok(evaluation.measure(rng)),
+            Err(EvalError::Unsupported(_)) => Ok(self.simulate::<StateVector>()?.measure(rng)),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+#[test]
+fn evaluate_test() {
+    use num::{One, Zero};
+    use super::gate::{Input, Output, Not, Hadamard};
+    use metrics::{approx_eq, TOLERANCE};
+    use std::f64::consts::PI;
+    let mut game = Game::new();
+    let input = game.add(Input::new(), ());
+    let not = game.add(Not::new(), ());
+    let output = game.add(Output::new(), ());
+    assert_eq!(Err(EvalError::Unconnected(port(not, 0))), game.evaluate().map(|e| e.outputs));
+    game.connect(port(not, 0), port(output, 0));
+    game.connect(port(input, 0), port(not, 0));
+    let result = game.evaluate().unwrap();
+    assert_eq!(vec![(output, 0)], result.outputs);
+    assert_eq!(&[Complex::zero(), Complex::one()], result.state.amplitudes());
+
+    let mut game = Game::new();
+    let input = game.add(Input::with_angles(PI / 2., 0.), ());
+    let h = game.add(Hadamard::new(), ());
+    let output = game.add(Output::new(), ());
+    game.connect(port(input, 0), port(h, 0));
+    game.connect(port(h, 0), port(output, 0));
+    let state = game.evaluate().unwrap().state;
+    let expected = DMatrix::from_column_vector(2, 1, &[Complex::one(), Complex::zero()]);
+    assert!(approx_eq(&expected, &state.mat(), TOLERANCE));
+
+    let second = game.add(Output::new(), ());
+    game.connect(port(h, 0), port(second, 0));
+    assert_eq!(Err(EvalError::FanOut(port(h, 0))), game.evaluate().map(|e| e.outputs));
+
+    // `connect` takes any port number, but the gates only have so many.
+    game.disconnect(port(second, 0));
+    game.connect(port(h, 0), port(second, 2));
+    assert_eq!(Err(EvalError::NoPort(port(second, 2))), game.evaluate().map(|e| e.outputs));
+    game.disconnect(port(second, 2));
+    game.connect(port(h, 1), port(second, 0));
+    assert_eq!(Err(EvalError::NoPort(port(h, 1))), game.unitary());
+}
+
+#[test]
+fn entangled_wires_test() {
+    use num::Zero;
+    use super::gate::{Input, Output, Not, Hadamard, ControlNot, PauliZ};
+    use metrics::{approx_eq, TOLERANCE};
+    let mut game = Game::new();
+    let a = game.add(Input::new(), ());
+    let b = game.add(Input::new(), ());
+    let h = game.add(Hadamard::new(), ());
+    let cnot = game.add(ControlNot::new(), ());
+    let x = game.add(Not::new(), ());
+    let z = game.add(PauliZ::new(), ());
+    let out_a = game.add(Output::new(), ());
+    let out_b = game.add(Output::new(), ());
+    game.connect(port(a, 0), port(h, 0));
+    game.connect(port(h, 0), port(cnot, 0));
+    game.connect(port(b, 0), port(cnot, 1));
+    game.connect(port(cnot, 0), port(z, 0));
+    game.connect(port(cnot, 1), port(x, 0));
+    game.connect(port(z, 0), port(out_a, 0));
+    game.connect(port(x, 0), port(out_b, 0));
+    assert_eq!(vec![port(a, 0), port(b, 0)], game.schedule().unwrap().inputs());
+    let result = game.evaluate().unwrap();
+    assert_eq!(Some(0), result.qubit(out_a));
+    assert_eq!(Some(1), result.qubit(out_b));
+    let y = Complex::new(1. / 2f64.sqrt(), 0.);
+    let expected = DMatrix::from_column_vector(4, 1, &[Complex::zero(), y, -y, Complex::zero()]);
+    assert!(approx_eq(&expected, &result.state.mat(), TOLERANCE));
+    let half = Complex::new(0.5, 0.);
+    let expected = DMatrix::from_column_vector(2, 2, &[half, Complex::zero(), Complex::zero(), half]);
+    assert!(approx_eq(&expected, &result.output_state(out_b).unwrap(), TOLERANCE));
+
+    // Crossing the wires at the outputs only changes which qubit ends up where.
+    game.connect(port(z, 0), port(out_b, 0));
+    game.connect(port(x, 0), port(out_a, 0));
+    let result = game.evaluate().unwrap();
+    assert_eq!(Some(1), result.qubit(out_a));
+    assert_eq!(Some(0), result.qubit(out_b));
+}
+
+#[test]
+fn unitary_test() {
+    use super::gate::{Input, Output, Hadamard, ControlNot, Toffoli};
+    use {control_not, swap, toffoli, apply_to_qubit, hadamard};
+    use metrics::{approx_eq, TOLERANCE};
+    let mut game = Game::new();
+    let a = game.add(Input::new(), ());
+    let b = game.add(Input::new(), ());
+    let cnot = game.add(ControlNot::new(), ());
+    let out_a = game.add(Output::new(), ());
+    game.connect(port(a, 0), port(cnot, 0));
+    game.connect(port(b, 0), port(cnot, 1));
+    game.connect(port(cnot, 0), port(out_a, 0));
+    assert_eq!(Err(EvalError::Unterminated(port(cnot, 1))), game.unitary());
+    let out_b = game.add(Output::new(), ());
+    game.connect(port(cnot, 1), port(out_b, 0));
+    assert_eq!(Ok(control_not(0, 1, 2)), game.unitary());
+    game.connect(port(cnot, 1), port(out_a, 0));
+    game.connect(port(cnot, 0), port(out_b, 0));
+    assert_eq!(Ok(swap(0, 1, 2) * control_not(0, 1, 2)), game.unitary());
+
+    let mut game = Game::new();
+    let inputs = (0..3).map(|_| game.add(Input::new(), ())).collect::<Vec<_>>();
+    let h = game.add(Hadamard::new(), ());
+    let t = game.add(Toffoli::new(), ());
+    let outputs = (0..3).map(|_| game.add(Output::new(), ())).collect::<Vec<_>>();
+    game.connect(port(inputs[0], 0), port(t, 2));
+    game.connect(port(inputs[1], 0), port(h, 0));
+    game.connect(port(h, 0), port(t, 0));
+    game.connect(port(inputs[2], 0), port(t, 1));
+    for i in 0..3 {
+        game.connect(port(t, i as u32), port(outputs[i], 0));
+    }
+    let expected = swap(1, 2, 3) * swap(0, 1, 3) * toffoli(1, 2, 0, 3) * apply_to_qubit(hadamard(), 1, 3);
+    let unitary = game.unitary().unwrap();
+    assert!(approx_eq(&expected, &unitary, TOLERANCE));
+}
+
+#[test]
+fn measure_test() {
+    use rand::{StdRng, SeedableRng};
+    use super::gate::{Input, Output, Hadamard, ControlNot, T};
+    use std::f64::consts::PI;
+    let mut rng = StdRng::from_seed(&[8][..]);
+    // Too many qubits for a state vector, so this only works on the tableau.
+    let n = 100;
+    let mut game = Game::new();
+    let inputs = (0..n).map(|_| game.add(Input::new(), ())).collect::<Vec<_>>();
+    let outputs = (0..n).map(|_| game.add(Output::new(), ())).collect::<Vec<_>>();
+    let h = game.add(Hadamard::new(), ());
+    game.connect(port(inputs[0], 0), port(h, 0));
+    let mut last = port(h, 0);
+    for i in 1..n {
+        let cnot = game.add(ControlNot::new(), ());
+        game.connect(last, port(cnot, 0));
+        game.connect(port(inputs[i], 0), port(cnot, 1));
+        game.connect(port(cnot, 0), port(outputs[i - 1], 0));
+        last = port(cnot, 1);
+    }
+    game.connect(last, port(outputs[n - 1], 0));
+    let result = game.measure(&mut rng).unwrap();
+    assert_eq!(n, result.len());
+    assert!(result.iter().all(|r| r.1 == result[0].1));
+
+    // A T gate falls back to the state vector, an input of |1⟩ works on both.
+    let mut game = Game::new();
+    let a = game.add(Input::with_angles(PI, 0.), ());
+    let out = game.add(Output::new(), ());
+    game.connect(port(a, 0), port(out, 0));
+    assert_eq!(Ok(vec![(out, 1)]), game.measure(&mut rng));
+    let t = game.add(T::new(), ());
+    game.connect(port(a, 0), port(t, 0));
+    game.connect(port(t, 0), port(out, 0));
+    assert_eq!(Ok(vec![(out, 1)]), game.measure(&mut rng));
+}
@@ -3,10 +3,236 @@ use num::Complex;
 
 use std::f64;
 
+use linalg::unitarity_deviation;
+
 pub trait Gate {
     fn max_in(&self) -> u32;
     fn max_out(&self) -> u32;
     fn process(&self, &[DMatrix<Complex<f64>>]) -> Vec<DMatrix<Complex<f64>>>;
+    /// Unitary acting on the joint state of the input ports, port 0 being the
+    /// most significant qubit. Output port `i` carries the qubit of input port `i`.
+    fn matrix(&self) -> Option<DMatrix<Complex<f64>>> {
+        None
+    }
+    /// Which of the gates in this module this is, with its parameters.
+    fn kind(&self) -> Kind {
+        Kind::Custom
+    }
+}
+
+/// Identifies a gate so that code like the stabilizer simulator can handle
+/// known gates specially. Gates from outside this module are `Custom`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Kind {
+    Input { theta: f64, phi: f64 },
+    Output,
+    Not,
+    PauliY,
+    PauliZ,
+    Hadamard,
+    S,
+    SDagger,
+    T,
+    TDagger,
+    Sx,
+    Rx(f64),
+    Ry(f64),
+    Rz(f64),
+    Phase(f64),
+    U3(f64, f64, f64),
+    ControlNot,
+    Swap,
+    ISwap,
+    Toffoli,
+    Fredkin,
+    Controlled {
+        controls: u32,
+        negative: Vec<u32>,
+        #[serde(with = "matrix_serde")]
+        target: DMatrix<Complex<f64>>,
+    },
+    Custom,
+}
+
+impl Kind {
+    /// Whether the gate maps stabilizer states to stabilizer states. For
+    /// `Input` this means that it prepares a stabilizer state. This is only a
+    /// quick check, `Tableau::apply` still refuses anything it can't simulate.
+    pub fn is_clifford(&self) -> bool {
+        match *self {
+            Kind::Input { theta, phi } => match quarter_turns(theta) {
+                Some(t) if t % 2 == 1 => quarter_turns(phi).is_some(),
+                Some(_) => true,
+                None => false,
+            },
+            Kind::Output | Kind::Not | Kind::PauliY | Kind::PauliZ | Kind::Hadamard | Kind::S |
+            Kind::SDagger | Kind::Sx | Kind::ControlNot | Kind::Swap | Kind::ISwap => true,
+            Kind::Rx(theta) | Kind::Ry(theta) | Kind::Rz(theta) | Kind::Phase(theta) => quarter_turns(theta).is_some(),
+            Kind::U3(theta, phi, lambda) => [theta, phi, lambda].iter().all(|&a| quarter_turns(a).is_some()),
+            Kind::T | Kind::TDagger | Kind::Toffoli | Kind::Fredkin | Kind::Controlled { .. } | Kind::Custom => false,
+        }
+    }
+
+    /// Gate of this kind, or `None` for `Custom`.
+    pub fn gate(&self) -> Option<Box<Gate>> {
+        Some(match *self {
+            Kind::Input { theta, phi } => Input::with_angles(theta, phi),
+            Kind::Output => Output::new(),
+            Kind::Not => Not::new(),
+            Kind::PauliY => PauliY::new(),
+            Kind::PauliZ => PauliZ::new(),
+            Kind::Hadamard => Hadamard::new(),
+            Kind::S => S::new(),
+            Kind::SDagger => SDagger::new(),
+            Kind::T => T::new(),
+            Kind::TDagger => TDagger::new(),
+            Kind::Sx => Sx::new(),
+            Kind::Rx(theta) => Rx::new(theta),
+            Kind::Ry(theta) => Ry::new(theta),
+            Kind::Rz(theta) => Rz::new(theta),
+            Kind::Phase(phi) => Phase::new(phi),
+            Kind::U3(theta, phi, lambda) => U3::new(theta, phi, lambda),
+            Kind::ControlNot => ControlNot::new(),
+            Kind::Swap => Swap::new(),
+            Kind::ISwap => ISwap::new(),
+            Kind::Toffoli => Toffoli::new(),
+            Kind::Fredkin => Fredkin::new(),
+            Kind::Controlled { controls, ref negative, ref target } => Controlled::new(controls, negative.clone(), target.clone()),
+            Kind::Custom => return None,
+        })
+    }
+}
+
+/// Stores the target of `Kind::Controlled` as rows of (re, im) pairs.
+mod matrix_serde {
+    use nalgebra::DMatrix;
+    use num::Complex;
+    use serde::{Serialize, Serializer, Deserialize, Deserializer};
+    use serde::de::Error;
+
+    pub fn serialize<S: Serializer>(m: &DMatrix<Complex<f64>>, serializer: S) -> Result<S::Ok, S::Error> {
+        let rows = (0..m.nrows())
+            .map(|r| (0..m.ncols()).map(|c| (m[(r, c)].re, m[(r, c)].im)).collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        rows.serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<DMatrix<Complex<f64>>, D::Error> {
+        let rows = Vec::<Vec<(f64, f64)>>::deserialize(deserializer)?;
+        let cols = rows.first().map_or(0, |r| r.len());
+        if rows.iter().any(|r| r.len() != cols) {
+            return Err(D::Error::custom("matrix rows differ in length"));
+        }
+        let mut result = DMatrix::new_zeros(rows.len(), cols);
+        for (r, row) in rows.iter().enumerate() {
+            for (c, &(re, im)) in row.iter().enumerate() {
+                result[(r, c)] = Complex::new(re, im);
+            }
+        }
+        Ok(result)
+    }
+}
+
+/// Number of quarter turns, modulo four, that `angle` makes when it is a
+/// multiple of π/2.
+pub fn quarter_turns(angle: f64) -> Option<usize> {
+    let turns = angle / f64::consts::FRAC_PI_2;
+    let rounded = turns.round();
+    if (turns - rounded).abs() < 1e-9 {
+        Some((((rounded as i64) % 4 + 4) % 4) as usize)
+    } else {
+        None
+    }
+}
+
+/// Why a gate can't be simulated.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GateError {
+    /// The gate's matrix has this many rows and columns.
+    NotSquare { rows: usize, cols: usize },
+    /// The gate's matrix is `size` x `size`, but its `ports` ports need 2^ports.
+    WrongDimension { size: usize, ports: u32 },
+    /// The gate has a matrix but a different number of inputs and outputs.
+    PortMismatch { inputs: u32, outputs: u32 },
+    /// The gate's matrix isn't unitary, the largest entry of U†U - I being this big.
+    NotUnitary { deviation: f64 },
+    /// A `Controlled` gate lists this port as a negative control, but it only
+    /// has `controls` controls.
+    NotControl { port: u32, controls: u32 },
+    /// A `Controlled` gate has this many ports, more than `MAX_CONTROLLED_PORTS`.
+    TooManyPorts { ports: u32 },
+}
+
+/// Most ports a `Controlled` gate can have, which keeps its matrix at a size
+/// that can be built.
+pub const MAX_CONTROLLED_PORTS: u32 = 12;
+
+/// Number of target ports of a `Controlled` gate with these arguments, or
+/// why they don't make a gate.
+fn check_controlled(controls: u32, negative: &[u32], target: &DMatrix<Complex<f64>>) -> Result<u32, GateError> {
+    if target.nrows() != target.ncols() {
+        return Err(GateError::NotSquare {
+            rows: target.nrows(),
+            cols: target.ncols(),
+        });
+    }
+    let targets = target.nrows().trailing_zeros();
+    if target.nrows() < 2 || !target.nrows().is_power_of_two() {
+        return Err(GateError::WrongDimension {
+            size: target.nrows(),
+            ports: targets,
+        });
+    }
+    if let Some(&port) = negative.iter().find(|&&c| c >= controls) {
+        return Err(GateError::NotControl {
+            port: port,
+            controls: controls,
+        });
+    }
+    if controls > MAX_CONTROLLED_PORTS || controls + targets > MAX_CONTROLLED_PORTS {
+        return Err(GateError::TooManyPorts {
+            ports: controls.saturating_add(targets),
+        });
+    }
+    Ok(targets)
+}
+
+/// Checks that the matrix of `gate` is a unitary acting on its ports, allowing
+/// `tolerance` of rounding error. Gates without a matrix are always fine.
+pub fn validate(gate: &Gate, tolerance: f64) -> Result<(), GateError> {
+    // A malformed `Controlled` has no matrix, which would otherwise pass.
+    if let Kind::Controlled { controls, ref negative, ref target } = gate.kind() {
+        check_controlled(controls, negative, target)?;
+    }
+    let matrix = match gate.matrix() {
+        Some(m) => m,
+        None => return Ok(()),
+    };
+    if gate.max_in() != gate.max_out() {
+        return Err(GateError::PortMismatch {
+            inputs: gate.max_in(),
+            outputs: gate.max_out(),
+        });
+    }
+    if matrix.nrows() != matrix.ncols() {
+        return Err(GateError::NotSquare {
+            rows: matrix.nrows(),
+            cols: matrix.ncols(),
+        });
+    }
+    if gate.max_in() >= 32 || matrix.nrows() != 1 << gate.max_in() {
+        return Err(GateError::WrongDimension {
+            size: matrix.nrows(),
+            ports: gate.max_in(),
+        });
+    }
+    let deviation = unitarity_deviation(&matrix);
+    if deviation > tolerance {
+        return Err(GateError::NotUnitary {
+            deviation: deviation,
+        });
+    }
+    Ok(())
 }
 
 #[derive(Clone)]
@@ -22,6 +248,13 @@ impl Input {
             phi: 0.,
         })
     }
+
+    pub fn with_angles(theta: f64, phi: f64) -> Box<Gate> {
+        Box::new(Input{
+            theta: theta,
+            phi: phi,
+        })
+    }
 }
 
 impl Gate for Input {
@@ -37,6 +270,9 @@ impl Gate for Input {
             C::new((self.theta / 2.).cos(), 0.),
             C::new(f64::consts::E, 0.).powc(C::new(0., self.phi)) * (self.theta / 2.).sin()])]
     }
+    fn kind(&self) -> Kind {
+        Kind::Input { theta: self.theta, phi: self.phi }
+    }
 }
 
 #[derive(Clone)]
@@ -58,6 +294,9 @@ impl Gate for Output {
     fn process(&self, _: &[DMatrix<Complex<f64>>]) -> Vec<DMatrix<Complex<f64>>> {
         vec![]
     }
+    fn kind(&self) -> Kind {
+        Kind::Output
+    }
 }
 
 #[derive(Clone)]
@@ -83,6 +322,12 @@ impl Gate for Not {
             vec![]
         }
     }
+    fn matrix(&self) -> Option<DMatrix<Complex<f64>>> {
+        Some(::not())
+    }
+    fn kind(&self) -> Kind {
+        Kind::Not
+    }
 }
 
 
@@ -109,4 +354,344 @@ impl Gate for ControlNot {
             vec![]
         }
     }
+    fn matrix(&self) -> Option<DMatrix<Complex<f64>>> {
+        Some(::control_not(0, 1, 2))
+    }
+    fn kind(&self) -> Kind {
+        Kind::ControlNot
+    }
+}
+
+macro_rules! single_qubit_gate {
+    (@impl $name:ident [$($field:ident),*] $mat:expr, $kind:expr) => {
+        impl Gate for $name {
+            fn max_in(&self) -> u32 {
+                1
+            }
+            fn max_out(&self) -> u32 {
+                1
+            }
+            fn process(&self, input: &[DMatrix<Complex<f64>>]) -> Vec<DMatrix<Complex<f64>>> {
+                if input.len() == 1 {
+                    vec![self.matrix().unwrap() * input[0].clone()]
+                } else {
+                    vec![]
+                }
+            }
+            fn matrix(&self) -> Option<DMatrix<Complex<f64>>> {
+                $(let $field = self.$field;)*
+                Some($mat)
+            }
+            fn kind(&self) -> Kind {
+                $(let $field = self.$field;)*
+                $kind
+            }
+        }
+    };
+    ($name:ident, $mat:expr, $kind:expr) => {
+        #[derive(Clone)]
+        pub struct $name;
+
+        impl $name {
+            pub fn new() -> Box<Gate> {
+                Box::new($name)
+            }
+        }
+
+        single_qubit_gate!(@impl $name [] $mat, $kind);
+    };
+    ($name:ident($($field:ident),*) => $mat:expr, $kind:expr) => {
+        #[derive(Clone)]
+        pub struct $name {
+            $($field: f64),*
+        }
+
+        impl $name {
+            pub fn new($($field: f64),*) -> Box<Gate> {
+                Box::new($name{
+                    $($field: $field),*
+                })
+            }
+        }
+
+        single_qubit_gate!(@impl $name [$($field),*] $mat, $kind);
+    };
+}
+
+single_qubit_gate!(PauliY, ::pauli_y(), Kind::PauliY);
+single_qubit_gate!(PauliZ, ::pauli_z(), Kind::PauliZ);
+single_qubit_gate!(Hadamard, ::hadamard(), Kind::Hadamard);
+single_qubit_gate!(S, ::s(), Kind::S);
+single_qubit_gate!(SDagger, ::s_dagger(), Kind::SDagger);
+single_qubit_gate!(T, ::t(), Kind::T);
+single_qubit_gate!(TDagger, ::t_dagger(), Kind::TDagger);
+single_qubit_gate!(Sx, ::sqrt_not(), Kind::Sx);
+single_qubit_gate!(Rx(theta) => ::rx(theta), Kind::Rx(theta));
+single_qubit_gate!(Ry(theta) => ::ry(theta), Kind::Ry(theta));
+single_qubit_gate!(Rz(theta) => ::rz(theta), Kind::Rz(theta));
+single_qubit_gate!(Phase(phi) => ::phase(phi), Kind::Phase(phi));
+single_qubit_gate!(U3(theta, phi, lambda) => ::u3(theta, phi, lambda), Kind::U3(theta, phi, lambda));
+
+/// `target` gated on the first `controls` ports, which trigger on 1 unless listed
+/// in `negative`. The ports after the controls are the target qubits.
+#[derive(Clone)]
+pub struct Controlled {
+    controls: u32,
+    negative: Vec<u32>,
+    target: DMatrix<Complex<f64>>,
+}
+
+impl Controlled {
+    /// `target` has to be square with a power of two rows, `negative` may only
+    /// list control ports and the gate can have at most `MAX_CONTROLLED_PORTS`
+    /// ports. Other arguments still make a gate, but it has no matrix and
+    /// `validate` rejects it. `try_new` checks them up front.
+    pub fn new(controls: u32, negative: Vec<u32>, target: DMatrix<Complex<f64>>) -> Box<Gate> {
+        Box::new(Controlled{
+            controls: controls,
+            negative: negative,
+            target: target,
+        })
+    }
+
+    /// Like `new`, but fails on arguments that don't make a proper gate.
+    pub fn try_new(controls: u32, negative: Vec<u32>, target: DMatrix<Complex<f64>>) -> Result<Box<Gate>, GateError> {
+        check_controlled(controls, &negative, &target)?;
+        Ok(Controlled::new(controls, negative, target))
+    }
+
+    fn targets(&self) -> u32 {
+        self.target.nrows().trailing_zeros()
+    }
+
+    fn mat(&self) -> DMatrix<Complex<f64>> {
+        let qubits = (self.controls + self.targets()) as usize;
+        let positive = (0..self.controls as usize)
+            .filter(|c| !self.negative.contains(&(*c as u32)))
+            .collect::<Vec<_>>();
+        let negative = self.negative.iter().map(|&c| c as usize).collect::<Vec<_>>();
+        let targets = (self.controls as usize..qubits).collect::<Vec<_>>();
+        ::controlled(&positive, &negative, &self.target, &targets, qubits)
+    }
+}
+
+impl Gate for Controlled {
+    fn max_in(&self) -> u32 {
+        self.controls.saturating_add(self.targets())
+    }
+    fn max_out(&self) -> u32 {
+        self.controls.saturating_add(self.targets())
+    }
+    fn process(&self, input: &[DMatrix<Complex<f64>>]) -> Vec<DMatrix<Complex<f64>>> {
+        match self.matrix() {
+            Some(m) if input.len() == 1 => vec![m * input[0].clone()],
+            _ => vec![],
+        }
+    }
+    fn matrix(&self) -> Option<DMatrix<Complex<f64>>> {
+        match check_controlled(self.controls, &self.negative, &self.target) {
+            Ok(_) => Some(self.mat()),
+            Err(_) => None,
+        }
+    }
+    fn kind(&self) -> Kind {
+        Kind::Controlled {
+            controls: self.controls,
+            negative: self.negative.clone(),
+            target: self.target.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Swap;
+
+impl Swap {
+    pub fn new() -> Box<Gate> {
+        Box::new(Swap)
+    }
+}
+
+impl Gate for Swap {
+    fn max_in(&self) -> u32 {
+        2
+    }
+    fn max_out(&self) -> u32 {
+        2
+    }
+    fn process(&self, input: &[DMatrix<Complex<f64>>]) -> Vec<DMatrix<Complex<f64>>> {
+        if input.len() == 1 {
+            vec![::swap(0, 1, 2) * input[0].clone()]
+        } else {
+            vec![]
+        }
+    }
+    fn matrix(&self) -> Option<DMatrix<Complex<f64>>> {
+        Some(::swap(0, 1, 2))
+    }
+    fn kind(&self) -> Kind {
+        Kind::Swap
+    }
+}
+
+#[derive(Clone)]
+pub struct ISwap;
+
+impl ISwap {
+    pub fn new() -> Box<Gate> {
+        Box::new(ISwap)
+    }
+}
+
+impl Gate for ISwap {
+    fn max_in(&self) -> u32 {
+        2
+    }
+    fn max_out(&self) -> u32 {
+        2
+    }
+    fn process(&self, input: &[DMatrix<Complex<f64>>]) -> Vec<DMatrix<Complex<f64>>> {
+        if input.len() == 1 {
+            vec![::iswap(0, 1, 2) * input[0].clone()]
+        } else {
+            vec![]
+        }
+    }
+    fn matrix(&self) -> Option<DMatrix<Complex<f64>>> {
+        Some(::iswap(0, 1, 2))
+    }
+    fn kind(&self) -> Kind {
+        Kind::ISwap
+    }
+}
+
+/// Flips port 2 when ports 0 and 1 are both 1.
+#[derive(Clone)]
+pub struct Toffoli;
+
+impl Toffoli {
+    pub fn new() -> Box<Gate> {
+        Box::new(Toffoli)
+    }
+}
+
+impl Gate for Toffoli {
+    fn max_in(&self) -> u32 {
+        3
+    }
+    fn max_out(&self) -> u32 {
+        3
+    }
+    fn process(&self, input: &[DMatrix<Complex<f64>>]) -> Vec<DMatrix<Complex<f64>>> {
+        if input.len() == 1 {
+            vec![::toffoli(0, 1, 2, 3) * input[0].clone()]
+        } else {
+            vec![]
+        }
+    }
+    fn matrix(&self) -> Option<DMatrix<Complex<f64>>> {
+        Some(::toffoli(0, 1, 2, 3))
+    }
+    fn kind(&self) -> Kind {
+        Kind::Toffoli
+    }
+}
+
+/// Swaps ports 1 and 2 when port 0 is 1.
+#[derive(Clone)]
+pub struct Fredkin;
+
+impl Fredkin {
+    pub fn new() -> Box<Gate> {
+        Box::new(Fredkin)
+    }
+}
+
+impl Gate for Fredkin {
+    fn max_in(&self) -> u32 {
+        3
+    }
+    fn max_out(&self) -> u32 {
+        3
+    }
+    fn process(&self, input: &[DMatrix<Complex<f64>>]) -> Vec<DMatrix<Complex<f64>>> {
+        if input.len() == 1 {
+            vec![::fredkin(0, 1, 2, 3) * input[0].clone()]
+        } else {
+            vec![]
+        }
+    }
+    fn matrix(&self) -> Option<DMatrix<Complex<f64>>> {
+        Some(::fredkin(0, 1, 2, 3))
+    }
+    fn kind(&self) -> Kind {
+        Kind::Fredkin
+    }
+}
+
+#[test]
+fn controlled_try_new_test() {
+    use nalgebra::Eye;
+    assert!(Controlled::try_new(2, vec![1], ::hadamard()).is_ok());
+    let error = |controls, negative, target| Controlled::try_new(controls, negative, target).map(|_| ()).unwrap_err();
+    assert_eq!(GateError::NotSquare { rows: 2, cols: 4 }, error(1, vec![], DMatrix::new_zeros(2, 4)));
+    assert_eq!(GateError::WrongDimension { size: 3, ports: 0 }, error(1, vec![], DMatrix::new_identity(3)));
+    assert_eq!(GateError::WrongDimension { size: 0, ports: 64 }, error(1, vec![], DMatrix::new_zeros(0, 0)));
+    assert_eq!(GateError::NotControl { port: 2, controls: 2 }, error(2, vec![2], ::not()));
+    assert_eq!(GateError::TooManyPorts { ports: 41 }, error(40, vec![], ::not()));
+    // Malformed gates can still be made with `new`, they just have no matrix.
+    let gate = Controlled::new(1, vec![1], ::not());
+    assert!(gate.matrix().is_none());
+    assert!(gate.process(&[DMatrix::new_zeros(4, 1)]).is_empty());
+}
+
+#[test]
+fn validate_test() {
+    use super::Game;
+    struct Custom(u32, u32, DMatrix<Complex<f64>>);
+    impl Gate for Custom {
+        fn max_in(&self) -> u32 {
+            self.0
+        }
+        fn max_out(&self) -> u32 {
+            self.1
+        }
+        fn process(&self, _: &[DMatrix<Complex<f64>>]) -> Vec<DMatrix<Complex<f64>>> {
+            vec![]
+        }
+        fn matrix(&self) -> Option<DMatrix<Complex<f64>>> {
+            Some(self.2.clone())
+        }
+    }
+    let gates = vec![Input::new(), Output::new(), Not::new(), ControlNot::new(), Hadamard::new(), T::new(),
+        Rx::new(0.3), U3::new(1., 2., 3.), Controlled::new(2, vec![1], ::hadamard()), Swap::new(), ISwap::new(),
+        Toffoli::new(), Fredkin::new()];
+    for gate in gates {
+        assert_eq!(Ok(()), validate(&*gate, 0.000001));
+    }
+    assert_eq!(Err(GateError::PortMismatch { inputs: 1, outputs: 2 }),
+        validate(&Custom(1, 2, ::not()), 0.000001));
+    assert_eq!(Err(GateError::NotSquare { rows: 2, cols: 1 }),
+        validate(&Custom(1, 1, DMatrix::new_zeros(2, 1)), 0.000001));
+    assert_eq!(Err(GateError::WrongDimension { size: 2, ports: 2 }),
+        validate(&Custom(2, 2, ::not()), 0.000001));
+    match validate(&*Controlled::new(1, vec![], ::not() * Complex::new(2., 0.)), 0.000001) {
+        Err(GateError::NotUnitary { deviation }) => assert!((deviation - 3.).abs() < 0.000001),
+        r => panic!("{:?}", r),
+    }
+
+    assert_eq!(Err(GateError::WrongDimension { size: 3, ports: 0 }),
+        validate(&*Controlled::new(1, vec![], DMatrix::new_zeros(3, 3)), 0.000001));
+    assert_eq!(Err(GateError::NotControl { port: 1, controls: 1 }),
+        validate(&*Controlled::new(1, vec![1], ::not()), 0.000001));
+
+    let mut game = Game::new();
+    assert!(game.try_add(Not::new(), ()).is_ok());
+    assert_eq!(Err(GateError::WrongDimension { size: 2, ports: 2 }),
+        game.try_add(Box::new(Custom(2, 2, ::not())), ()));
+    assert_eq!(Err(GateError::WrongDimension { size: 0, ports: 64 }),
+        game.try_add(Controlled::new(2, vec![], DMatrix::new_zeros(0, 0)), ()));
+    assert_eq!(Err(GateError::TooManyPorts { ports: 41 }),
+        game.try_add(Controlled::new(40, vec![], ::not()), ()));
+    assert_eq!(1, game.iter().count());
 }
@@ -15,12 +15,24 @@ use daggy::petgraph::graph;
 use daggy::petgraph::Bfs;
 
 use self::dag::PortNumbered;
-use self::gate::Gate;
+use self::gate::{Gate, GateError, validate};
+use metrics::TOLERANCE;
 
 pub use self::dag::{Edge, Port, port};
+pub use self::eval::{EvalError, Schedule, Evaluation};
+pub use self::transpile::{Basis, TranspileError};
+pub use self::equivalence::Equivalence;
+pub use self::save::{SaveError, LoadError, FORMAT_VERSION};
 
 pub mod gate;
+pub mod qasm;
 mod dag;
+mod eval;
+mod transpile;
+mod optimize;
+mod equivalence;
+mod save;
+mod dot;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum EventType {
@@ -75,6 +87,12 @@ impl<T> Game<T> {
         self.dag.add_node(Node::new(node, data))
     }
 
+    /// Like `add`, but rejects gates whose matrix isn't a unitary on their ports.
+    pub fn try_add(&mut self, node: Box<Gate>, data: T) -> Result<NodeIndex, GateError> {
+        validate(&*node, TOLERANCE)?;
+        Ok(self.add(node, data))
+    }
+
     pub fn remove(&mut self, node: &NodeIndex) -> Option<(Box<Gate>, T)> {
         let children = self.dag.children(*node).map(|n| n.1.node).collect::<Vec<_>>();
         self.dag.remove_outgoing_edges(*node);
@@ -108,6 +126,24 @@ impl<T> Game<T> {
     pub fn connections(&self) -> usize {
         self.dag.edge_count()
     }
+
+    /// A port that some edge uses but its gate doesn't have, if there is one.
+    /// `connect` doesn't check this, so code walking the ports has to.
+    fn missing_port(&self) -> Option<Port<u32>> {
+        let ports = |p: Port<u32>| {
+            let gate = &self.dag.node_weight(p.node).unwrap().process;
+            (gate.max_in(), gate.max_out())
+        };
+        for (src, trg) in self.iter_connections() {
+            if src.port >= ports(src).1 {
+                return Some(src);
+            }
+            if trg.port >= ports(trg).0 {
+                return Some(trg);
+            }
+        }
+        None
+    }
 }
 
 pub struct Node<T> {
@@ -132,3 +168,31 @@ impl<'a, T> Iterator for Iter<'a, T> {
         self.0.next().map(|n| (&*n.weight.process, &n.weight.data))
     }
 }
+
+/// Circuit over `qubits` wires, running the gates in order on the listed
+/// qubits. Inputs come first, then the gates numbered from 1 by their data
+/// and finally the outputs, so node indices follow the same order.
+#[cfg(test)]
+fn build(qubits: usize, gates: Vec<(Box<Gate>, Vec<usize>)>) -> Game<usize> {
+    use self::gate::{Input, Output};
+    let mut game = Game::new();
+    let mut wires = (0..qubits).map(|_| port(game.add(Input::new(), 0), 0)).collect::<Vec<_>>();
+    for (i, (gate, qubits)) in gates.into_iter().enumerate() {
+        let node = game.add(gate, i + 1);
+        for (p, &q) in qubits.iter().enumerate() {
+            game.connect(wires[q], port(node, p as u32));
+            wires[q] = port(node, p as u32);
+        }
+    }
+    for w in wires {
+        let output = game.add(Output::new(), 0);
+        game.connect(w, port(output, 0));
+    }
+    game
+}
+
+/// Single qubit circuit running the gates in order, see `build`.
+#[cfg(test)]
+fn line(gates: Vec<Box<Gate>>) -> Game<usize> {
+    build(1, gates.into_iter().map(|g| (g, vec![0])).collect())
+}
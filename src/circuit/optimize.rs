@@ -0,0 +1,240 @@
+use nalgebra::{DMatrix, Eye};
+use num::Complex;
+use daggy::NodeIndex;
+
+use super::Game;
+use super::gate::{self, Gate, Kind};
+use metrics::{eq_up_to_phase, TOLERANCE};
+
+impl<T> Game<T> {
+    /// Runs all of the passes below until none of them finds anything more to
+    /// remove, returning the removed gates with their data.
+    pub fn optimize(&mut self) -> Vec<(Box<Gate>, T)> {
+        let mut result = vec![];
+        loop {
+            let count = result.len();
+            result.extend(self.drop_identities());
+            result.extend(self.cancel_inverses());
+            result.extend(self.merge_rotations());
+            if result.len() == count {
+                return result;
+            }
+        }
+    }
+
+    /// Removes gates whose matrix is the identity up to global phase, such as
+    /// `Rz(0.)`, connecting their inputs straight to their outputs.
+    pub fn drop_identities(&mut self) -> Vec<(Box<Gate>, T)> {
+        let identities = self.dag.raw_nodes().iter()
+            .enumerate()
+            .filter(|&(_, n)| is_identity(&*n.weight.process))
+            .map(|(i, _)| NodeIndex::new(i))
+            .collect::<Vec<_>>();
+        self.bypass_all(identities)
+    }
+
+    /// Removes pairs of adjacent gates that undo each other, like X·X, H·H or
+    /// CNOT·CNOT. The second gate has to take every output of the first one
+    /// at the same port number, and the pair has to multiply to the identity
+    /// up to global phase.
+    pub fn cancel_inverses(&mut self) -> Vec<(Box<Gate>, T)> {
+        let mut used = vec![false; self.dag.node_count()];
+        let mut pairs = vec![];
+        for a in self.dag.topological_order() {
+            if used[a.index()] {
+                continue;
+            }
+            let b = match self.follower(a) {
+                Some(b) if !used[b.index()] => b,
+                _ => continue,
+            };
+            let first = &self.dag.node_weight(a).unwrap().process;
+            let second = &self.dag.node_weight(b).unwrap().process;
+            let cancels = match (first.matrix(), second.matrix()) {
+                (Some(x), Some(y)) => x.nrows() == y.nrows() && is_identity_matrix(&(y * x)),
+                _ => false,
+            };
+            if cancels {
+                used[a.index()] = true;
+                used[b.index()] = true;
+                pairs.push(a);
+                pairs.push(b);
+            }
+        }
+        self.bypass_all(pairs)
+    }
+
+    /// Folds runs of rotations about the same axis on a wire, like Rz(a)·Rz(b),
+    /// into the first rotation of the run and removes the others.
+    pub fn merge_rotations(&mut self) -> Vec<(Box<Gate>, T)> {
+        let mut used = vec![false; self.dag.node_count()];
+        let mut merged = vec![];
+        for a in self.dag.topological_order() {
+            if used[a.index()] {
+                continue;
+            }
+            let (axis, mut angle) = match rotation(&self.dag.node_weight(a).unwrap().process.kind()) {
+                Some(r) => r,
+                None => continue,
+            };
+            let mut last = a;
+            while let Some(b) = self.follower(last) {
+                match rotation(&self.dag.node_weight(b).unwrap().process.kind()) {
+                    Some((other, theta)) if other == axis && !used[b.index()] => {
+                        angle += theta;
+                        used[b.index()] = true;
+                        merged.push(b);
+                        last = b;
+                    }
+                    _ => break,
+                }
+            }
+            if last != a {
+                *self.get_process_mut(a).unwrap() = match axis {
+                    Axis::X => gate::Rx::new(angle),
+                    Axis::Y => gate::Ry::new(angle),
+                    Axis::Z => gate::Rz::new(angle),
+                    Axis::Phase => gate::Phase::new(angle),
+                };
+            }
+        }
+        self.bypass_all(merged)
+    }
+
+    /// Gate that takes every output of `node` at the same port number, if any.
+    fn follower(&self, node: NodeIndex) -> Option<NodeIndex> {
+        let outputs = self.dag.node_weight(node).unwrap().process.max_out();
+        let children = self.dag.children(node).collect::<Vec<_>>();
+        if outputs == 0 || children.len() != outputs as usize {
+            return None;
+        }
+        let next = children[0].1.node;
+        if self.dag.node_weight(next).unwrap().process.max_in() != outputs {
+            return None;
+        }
+        let mut seen = vec![false; outputs as usize];
+        for &(src, trg) in &children {
+            if trg.node != next || trg.port != src || src >= outputs || seen[src as usize] {
+                return None;
+            }
+            seen[src as usize] = true;
+        }
+        Some(next)
+    }
+
+    /// Connects the inputs of each of `nodes` straight to its outputs and then
+    /// removes them. Nodes are handled in the given order, so a node can feed
+    /// one that comes after it.
+    fn bypass_all(&mut self, nodes: Vec<NodeIndex>) -> Vec<(Box<Gate>, T)> {
+        for &node in &nodes {
+            let parents = self.dag.parents(node).collect::<Vec<_>>();
+            let children = self.dag.children(node).collect::<Vec<_>>();
+            for (src, trg) in children {
+                match parents.iter().find(|p| p.1 == src) {
+                    Some(&(from, _)) => {
+                        self.connect(from, trg);
+                    }
+                    None => {
+                        self.disconnect(trg);
+                    }
+                }
+            }
+        }
+        let mut sorted = nodes;
+        sorted.sort();
+        let mut removed = sorted.into_iter()
+            .rev()
+            .filter_map(|n| self.remove(&n))
+            .collect::<Vec<_>>();
+        removed.reverse();
+        removed
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Axis {
+    X,
+    Y,
+    Z,
+    Phase,
+}
+
+fn rotation(kind: &Kind) -> Option<(Axis, f64)> {
+    match *kind {
+        Kind::Rx(theta) => Some((Axis::X, theta)),
+        Kind::Ry(theta) => Some((Axis::Y, theta)),
+        Kind::Rz(theta) => Some((Axis::Z, theta)),
+        Kind::Phase(phi) => Some((Axis::Phase, phi)),
+        _ => None,
+    }
+}
+
+fn is_identity(gate: &Gate) -> bool {
+    gate.max_in() > 0 && gate.max_in() == gate.max_out() && gate.matrix().map_or(false, |m| is_identity_matrix(&m))
+}
+
+fn is_identity_matrix(m: &DMatrix<Complex<f64>>) -> bool {
+    m.nrows() == m.ncols() && eq_up_to_phase(m, &DMatrix::new_identity(m.nrows()), TOLERANCE)
+}
+
+#[test]
+fn cancel_inverses_test() {
+    use super::{port, build, line};
+    use super::gate::{Not, Hadamard, ControlNot, S, SDagger, T};
+    let mut game = line(vec![Not::new(), Hadamard::new(), Hadamard::new(), T::new(), S::new(), SDagger::new(), Not::new()]);
+    let before = game.unitary().unwrap();
+    let removed = game.cancel_inverses().into_iter().map(|r| r.1).collect::<Vec<_>>();
+    assert_eq!(vec![2, 3, 5, 6], removed);
+    assert_eq!(5, game.iter().count());
+    assert!(eq_up_to_phase(&before, &game.unitary().unwrap(), 0.000001));
+
+    // The CNOTs below share both wires but with the ports crossed, so they stay.
+    let mut game = build(2, vec![
+        (ControlNot::new(), vec![0, 1]),
+        (ControlNot::new(), vec![1, 0]),
+        (ControlNot::new(), vec![1, 0]),
+    ]);
+    let before = game.unitary().unwrap();
+    assert_eq!(2, game.cancel_inverses().len());
+    assert_eq!(5, game.iter().count());
+    assert!(eq_up_to_phase(&before, &game.unitary().unwrap(), 0.000001));
+    assert_eq!(0, game.cancel_inverses().len());
+
+    // Edges on ports the gates don't have are left for `schedule` to report.
+    let mut game = line(vec![Not::new(), Not::new()]);
+    let (first, second) = (NodeIndex::new(1), NodeIndex::new(2));
+    game.disconnect(port(second, 0));
+    game.connect(port(first, 3), port(second, 3));
+    assert_eq!(0, game.cancel_inverses().len());
+}
+
+#[test]
+fn merge_rotations_test() {
+    use std::f64::consts::PI;
+    use super::line;
+    use super::gate::{Rx, Rz, Phase, Hadamard};
+    let mut game = line(vec![Rz::new(0.25), Rz::new(0.5), Rz::new(1.), Hadamard::new(), Phase::new(PI), Phase::new(PI), Rx::new(0.5)]);
+    let before = game.unitary().unwrap();
+    let removed = game.merge_rotations().into_iter().map(|r| r.1).collect::<Vec<_>>();
+    assert_eq!(vec![2, 3, 6], removed);
+    assert_eq!(Some(Kind::Rz(1.75)), game.iter().find(|g| *g.1 == 1).map(|g| g.0.kind()));
+    assert!(eq_up_to_phase(&before, &game.unitary().unwrap(), 0.000001));
+
+    // Phase(2π) is left behind as an identity, which the full optimizer drops.
+    let removed = game.optimize().into_iter().map(|r| r.1).collect::<Vec<_>>();
+    assert_eq!(vec![5], removed);
+    assert!(eq_up_to_phase(&before, &game.unitary().unwrap(), 0.000001));
+}
+
+#[test]
+fn optimize_test() {
+    use super::line;
+    use super::gate::{Not, Hadamard, Rx, Controlled};
+    let mut game = line(vec![Not::new(), Hadamard::new(), Rx::new(0.), Hadamard::new(), Not::new(), Controlled::new(0, vec![], ::rz(0.3))]);
+    let before = game.unitary().unwrap();
+    let mut removed = game.optimize().into_iter().map(|r| r.1).collect::<Vec<_>>();
+    removed.sort();
+    assert_eq!(vec![1, 2, 3, 4, 5], removed);
+    assert_eq!(3, game.iter().count());
+    assert!(eq_up_to_phase(&before, &game.unitary().unwrap(), 0.000001));
+}
@@ -0,0 +1,1067 @@
+//! OpenQASM programs.
+
+use nalgebra::DMatrix;
+use num::Complex;
+use daggy::NodeIndex;
+
+use std::collections::HashMap;
+use std::f64::consts::PI;
+
+use super::{Game, Port, port, EvalError};
+use super::gate::{self, Gate, Kind};
+use super::transpile::zyz;
+use metrics::{approx_eq, TOLERANCE};
+use kronecker_product;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    /// Line of the offending token, starting from 1.
+    pub line: usize,
+    /// Column of the offending token in characters, starting from 1.
+    pub column: usize,
+    pub kind: ErrorKind,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ErrorKind {
+    /// Something else was expected here, such as `;` or an identifier.
+    Expected(&'static str),
+    UnexpectedCharacter(char),
+    UnexpectedEnd,
+    /// The program doesn't start with `OPENQASM 2.0;`.
+    UnsupportedVersion,
+    /// Only `qelib1.inc` can be included.
+    UnknownInclude(String),
+    /// No register, gate or parameter has this name.
+    Undefined(String),
+    /// A register or gate with this name already exists.
+    Redefined(String),
+    /// The index is past the end of the register.
+    OutOfRange { index: usize, size: usize },
+    WrongQubitCount { expected: usize, found: usize },
+    WrongParameterCount { expected: usize, found: usize },
+    /// Registers used together in one statement have different sizes.
+    SizeMismatch,
+    /// A gate gets the same qubit on two of its arguments.
+    RepeatedQubit,
+    /// The qubit has already been measured, which ends its wire.
+    Measured,
+    /// The statement is valid QASM that has no counterpart in a circuit here.
+    Unsupported(&'static str),
+}
+
+impl<T: Default> Game<T> {
+    /// Builds the circuit of an OpenQASM 2.0 program.
+    ///
+    /// Every qubit of a `qreg` gets an `Input`, in declaration order. Measuring
+    /// a qubit ends its wire at an `Output`, and qubits that are never measured
+    /// get an `Output` at the end of the program.
+    ///
+    /// Outputs are the only record of the classical bits, so every `creg` is
+    /// laid out after the previous one and each measurement has to go to the
+    /// next bit in that order.
+    ///
+    /// The gates of `qelib1.inc` are always available and `gate` definitions
+    /// are expanded where they're used. `barrier` is ignored, while `reset`,
+    /// `if` and `opaque` are unsupported.
+    pub fn from_qasm(source: &str) -> Result<Game<T>, ParseError> {
+        let mut importer = Importer {
+            parser: Parser::new(source)?,
+            game: Game::new(),
+            qregs: HashMap::new(),
+            cregs: HashMap::new(),
+            bits: 0,
+            measured: 0,
+            wires: vec![],
+            definitions: HashMap::new(),
+        };
+        importer.program()?;
+        Ok(importer.game)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Int(usize),
+    Real(f64),
+    Str(String),
+    Symbol(&'static str),
+}
+
+const SYMBOLS: [&'static str; 15] = ["->", "==", ";", ",", "[", "]", "(", ")", "{", "}", "+", "-", "*", "/", "^"];
+
+fn tokenize(source: &str) -> Result<Vec<(Token, usize, usize)>, ParseError> {
+    let chars = source.chars().collect::<Vec<_>>();
+    let mut tokens = vec![];
+    let (mut i, mut line, mut column) = (0, 1, 1);
+    while i < chars.len() {
+        let c = chars[i];
+        let start = i;
+        if c == '\n' {
+            i += 1;
+            line += 1;
+            column = 1;
+            continue;
+        } else if c.is_whitespace() {
+            i += 1;
+        } else if c == '/' && chars.get(i + 1) == Some(&'/') {
+            while i < chars.len() && chars[i] != '\n' {
+                i += 1;
+            }
+        } else if c.is_alphabetic() || c == '_' {
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            tokens.push((Token::Ident(chars[start..i].iter().cloned().collect()), line, column));
+        } else if c.is_digit(10) || (c == '.' && chars.get(i + 1).map_or(false, |c| c.is_digit(10))) {
+            let mut real = false;
+            while i < chars.len() && (chars[i].is_digit(10) || chars[i] == '.') {
+                real |= chars[i] == '.';
+                i += 1;
+            }
+            if i < chars.len() && (chars[i] == 'e' || chars[i] == 'E') {
+                real = true;
+                i += 1;
+                if i < chars.len() && (chars[i] == '+' || chars[i] == '-') {
+                    i += 1;
+                }
+                while i < chars.len() && chars[i].is_digit(10) {
+                    i += 1;
+                }
+            }
+            let text = chars[start..i].iter().cloned().collect::<String>();
+            let token = if real {
+                text.parse().ok().map(Token::Real)
+            } else {
+                text.parse().ok().map(Token::Int)
+            };
+            match token {
+                Some(t) => tokens.push((t, line, column)),
+                None => return Err(ParseError { line: line, column: column, kind: ErrorKind::Expected("number") }),
+            }
+        } else if c == '"' {
+            i += 1;
+            while i < chars.len() && chars[i] != '"' && chars[i] != '\n' {
+                i += 1;
+            }
+            if chars.get(i) != Some(&'"') {
+                return Err(ParseError { line: line, column: column, kind: ErrorKind::Expected("closing \"") });
+            }
+            i += 1;
+            tokens.push((Token::Str(chars[start + 1..i - 1].iter().cloned().collect()), line, column));
+        } else {
+            let symbol = SYMBOLS.iter().find(|s| s.chars().enumerate().all(|(k, s)| chars.get(i + k) == Some(&s)));
+            match symbol {
+                Some(s) => {
+                    i += s.len();
+                    tokens.push((Token::Symbol(s), line, column));
+                }
+                None => return Err(ParseError { line: line, column: column, kind: ErrorKind::UnexpectedCharacter(c) }),
+            }
+        }
+        column += i - start;
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<(Token, usize, usize)>,
+    pos: usize,
+    end: (usize, usize),
+}
+
+impl Parser {
+    fn new(source: &str) -> Result<Parser, ParseError> {
+        let lines = source.split('\n').collect::<Vec<_>>();
+        Ok(Parser {
+            tokens: tokenize(source)?,
+            pos: 0,
+            end: (lines.len(), lines.last().map_or(0, |l| l.chars().count()) + 1),
+        })
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|t| &t.0)
+    }
+
+    /// Line and column of the next token.
+    fn position(&self) -> (usize, usize) {
+        self.tokens.get(self.pos).map_or(self.end, |t| (t.1, t.2))
+    }
+
+    fn error<R>(&self, kind: ErrorKind) -> Result<R, ParseError> {
+        let (line, column) = self.position();
+        error_at((line, column), kind)
+    }
+
+    fn next(&mut self) -> Result<Token, ParseError> {
+        match self.tokens.get(self.pos).map(|t| t.0.clone()) {
+            Some(t) => {
+                self.pos += 1;
+                Ok(t)
+            }
+            None => self.error(ErrorKind::UnexpectedEnd),
+        }
+    }
+
+    fn is_symbol(&self, symbol: &str) -> bool {
+        match self.peek() {
+            Some(&Token::Symbol(s)) => s == symbol,
+            _ => false,
+        }
+    }
+
+    fn symbol(&mut self, symbol: &'static str) -> Result<(), ParseError> {
+        if self.is_symbol(symbol) {
+            self.pos += 1;
+            Ok(())
+        } else if self.peek().is_none() {
+            self.error(ErrorKind::UnexpectedEnd)
+        } else {
+            self.error(ErrorKind::Expected(symbol))
+        }
+    }
+
+    fn ident(&mut self) -> Result<String, ParseError> {
+        match self.peek().cloned() {
+            Some(Token::Ident(name)) => {
+                self.pos += 1;
+                Ok(name)
+            }
+            Some(_) => self.error(ErrorKind::Expected("identifier")),
+            None => self.error(ErrorKind::UnexpectedEnd),
+        }
+    }
+
+    fn int(&mut self) -> Result<usize, ParseError> {
+        match self.peek().cloned() {
+            Some(Token::Int(i)) => {
+                self.pos += 1;
+                Ok(i)
+            }
+            Some(_) => self.error(ErrorKind::Expected("integer")),
+            None => self.error(ErrorKind::UnexpectedEnd),
+        }
+    }
+
+    /// Comma separated list of `item` up to `end`, which isn't consumed.
+    fn list<R, F>(&mut self, end: &str, mut item: F) -> Result<Vec<R>, ParseError>
+        where F: FnMut(&mut Parser) -> Result<R, ParseError>
+    {
+        let mut result = vec![];
+        if self.is_symbol(end) {
+            return Ok(result);
+        }
+        loop {
+            result.push(item(self)?);
+            if !self.is_symbol(",") {
+                return Ok(result);
+            }
+            self.pos += 1;
+        }
+    }
+
+    /// Parameters in parentheses, if there are any.
+    fn parameters(&mut self) -> Result<Vec<Expr>, ParseError> {
+        if !self.is_symbol("(") {
+            return Ok(vec![]);
+        }
+        self.pos += 1;
+        let result = self.list(")", Parser::expr)?;
+        self.symbol(")")?;
+        Ok(result)
+    }
+
+    fn expr(&mut self) -> Result<Expr, ParseError> {
+        let mut result = self.term()?;
+        loop {
+            let op = if self.is_symbol("+") { '+' } else if self.is_symbol("-") { '-' } else { return Ok(result) };
+            self.pos += 1;
+            result = Expr::Binary(op, Box::new(result), Box::new(self.term()?));
+        }
+    }
+
+    fn term(&mut self) -> Result<Expr, ParseError> {
+        let mut result = self.power()?;
+        loop {
+            let op = if self.is_symbol("*") { '*' } else if self.is_symbol("/") { '/' } else { return Ok(result) };
+            self.pos += 1;
+            result = Expr::Binary(op, Box::new(result), Box::new(self.power()?));
+        }
+    }
+
+    fn power(&mut self) -> Result<Expr, ParseError> {
+        let base = self.unary()?;
+        if self.is_symbol("^") {
+            self.pos += 1;
+            Ok(Expr::Binary('^', Box::new(base), Box::new(self.power()?)))
+        } else {
+            Ok(base)
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr, ParseError> {
+        if self.is_symbol("-") {
+            self.pos += 1;
+            Ok(Expr::Negate(Box::new(self.unary()?)))
+        } else if self.is_symbol("+") {
+            self.pos += 1;
+            self.unary()
+        } else {
+            self.primary()
+        }
+    }
+
+    fn primary(&mut self) -> Result<Expr, ParseError> {
+        let position = self.position();
+        match self.next()? {
+            Token::Int(i) => Ok(Expr::Number(i as f64)),
+            Token::Real(x) => Ok(Expr::Number(x)),
+            Token::Symbol("(") => {
+                let result = self.expr()?;
+                self.symbol(")")?;
+                Ok(result)
+            }
+            Token::Ident(ref name) if name == "pi" => Ok(Expr::Number(PI)),
+            Token::Ident(name) => {
+                if ["sin", "cos", "tan", "exp", "ln", "sqrt"].contains(&&*name) && self.is_symbol("(") {
+                    self.pos += 1;
+                    let argument = self.expr()?;
+                    self.symbol(")")?;
+                    Ok(Expr::Function(name, Box::new(argument)))
+                } else {
+                    Ok(Expr::Parameter(name, position))
+                }
+            }
+            _ => error_at(position, ErrorKind::Expected("expression")),
+        }
+    }
+
+    /// Qubit or bit argument, either a whole register or one of its elements.
+    fn argument(&mut self) -> Result<Argument, ParseError> {
+        let position = self.position();
+        let name = self.ident()?;
+        let index = if self.is_symbol("[") {
+            self.pos += 1;
+            let index = self.int()?;
+            self.symbol("]")?;
+            Some(index)
+        } else {
+            None
+        };
+        Ok(Argument {
+            name: name,
+            index: index,
+            position: position,
+        })
+    }
+}
+
+fn error_at<R>((line, column): (usize, usize), kind: ErrorKind) -> Result<R, ParseError> {
+    Err(ParseError {
+        line: line,
+        column: column,
+        kind: kind,
+    })
+}
+
+#[derive(Debug, Clone)]
+enum Expr {
+    Number(f64),
+    Parameter(String, (usize, usize)),
+    Negate(Box<Expr>),
+    Binary(char, Box<Expr>, Box<Expr>),
+    Function(String, Box<Expr>),
+}
+
+impl Expr {
+    fn eval(&self, parameters: &HashMap<String, f64>) -> Result<f64, ParseError> {
+        Ok(match *self {
+            Expr::Number(x) => x,
+            Expr::Parameter(ref name, position) => match parameters.get(name) {
+                Some(&x) => x,
+                None => return error_at(position, ErrorKind::Undefined(name.clone())),
+            },
+            Expr::Negate(ref e) => -e.eval(parameters)?,
+            Expr::Binary(op, ref a, ref b) => {
+                let (a, b) = (a.eval(parameters)?, b.eval(parameters)?);
+                match op {
+                    '+' => a + b,
+                    '-' => a - b,
+                    '*' => a * b,
+                    '/' => a / b,
+                    _ => a.powf(b),
+                }
+            }
+            Expr::Function(ref name, ref e) => {
+                let x = e.eval(parameters)?;
+                match &**name {
+                    "sin" => x.sin(),
+                    "cos" => x.cos(),
+                    "tan" => x.tan(),
+                    "exp" => x.exp(),
+                    "ln" => x.ln(),
+                    _ => x.sqrt(),
+                }
+            }
+        })
+    }
+}
+
+struct Argument {
+    name: String,
+    index: Option<usize>,
+    position: (usize, usize),
+}
+
+/// Use of a gate inside a `gate` definition.
+struct Call {
+    name: String,
+    parameters: Vec<Expr>,
+    qubits: Vec<usize>,
+    position: (usize, usize),
+}
+
+struct Definition {
+    parameters: Vec<String>,
+    qubits: usize,
+    body: Vec<Call>,
+}
+
+struct Importer<T> {
+    parser: Parser,
+    game: Game<T>,
+    /// First qubit and size of each quantum register.
+    qregs: HashMap<String, (usize, usize)>,
+    /// First bit and size of each classical register.
+    cregs: HashMap<String, (usize, usize)>,
+    /// Number of classical bits declared so far.
+    bits: usize,
+    /// Number of bits measured so far, which is also the next one to measure.
+    measured: usize,
+    /// End of the wire of each qubit, or `None` once it has been measured.
+    wires: Vec<Option<Port<u32>>>,
+    definitions: HashMap<String, Definition>,
+}
+
+impl<T: Default> Importer<T> {
+    fn program(&mut self) -> Result<(), ParseError> {
+        let position = self.parser.position();
+        match self.parser.next()? {
+            Token::Ident(ref s) if s == "OPENQASM" => {}
+            _ => return error_at(position, ErrorKind::Expected("OPENQASM")),
+        }
+        let position = self.parser.position();
+        match self.parser.next()? {
+            Token::Int(2) => {}
+            Token::Real(v) if v >= 2. && v < 3. => {}
+            _ => return error_at(position, ErrorKind::UnsupportedVersion),
+        }
+        self.parser.symbol(";")?;
+        while self.parser.peek().is_some() {
+            self.statement()?;
+        }
+        for q in 0..self.wires.len() {
+            if let Some(wire) = self.wires[q] {
+                let output = self.game.add(gate::Output::new(), T::default());
+                self.game.connect(wire, port(output, 0));
+            }
+        }
+        Ok(())
+    }
+
+    fn statement(&mut self) -> Result<(), ParseError> {
+        let position = self.parser.position();
+        let keyword = self.parser.ident()?;
+        match &*keyword {
+            "include" => {
+                match self.parser.next()? {
+                    Token::Str(ref file) if file == "qelib1.inc" => {}
+                    Token::Str(file) => return error_at(position, ErrorKind::UnknownInclude(file)),
+                    _ => return error_at(position, ErrorKind::Expected("file name")),
+                }
+                self.parser.symbol(";")
+            }
+            "qreg" | "creg" => self.register(keyword == "qreg"),
+            "gate" => self.definition(),
+            "measure" => self.measure(),
+            "barrier" => {
+                self.parser.list(";", Parser::argument)?
+                    .iter()
+                    .map(|a| self.qubits(a))
+                    .collect::<Result<Vec<_>, _>>()?;
+                self.parser.symbol(";")
+            }
+            "opaque" => error_at(position, ErrorKind::Unsupported("opaque gates")),
+            "reset" => error_at(position, ErrorKind::Unsupported("reset")),
+            "if" => error_at(position, ErrorKind::Unsupported("classically controlled gates")),
+            _ => {
+                let parameters = self.parser.parameters()?
+                    .iter()
+                    .map(|e| e.eval(&HashMap::new()))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let arguments = self.parser.list(";", Parser::argument)?;
+                self.parser.symbol(";")?;
+                let registers = arguments.iter().map(|a| self.qubits(a)).collect::<Result<Vec<_>, _>>()?;
+                for qubits in broadcast(&registers, position)? {
+                    self.apply(&keyword, &parameters, &qubits, position)?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    fn register(&mut self, quantum: bool) -> Result<(), ParseError> {
+        let position = self.parser.position();
+        let name = self.parser.ident()?;
+        self.parser.symbol("[")?;
+        let size = self.parser.int()?;
+        self.parser.symbol("]")?;
+        self.parser.symbol(";")?;
+        if self.qregs.contains_key(&name) || self.cregs.contains_key(&name) {
+            return error_at(position, ErrorKind::Redefined(name));
+        }
+        if quantum {
+            self.qregs.insert(name, (self.wires.len(), size));
+            for _ in 0..size {
+                let input = self.game.add(gate::Input::new(), T::default());
+                self.wires.push(Some(port(input, 0)));
+            }
+        } else {
+            self.cregs.insert(name, (self.bits, size));
+            self.bits += size;
+        }
+        Ok(())
+    }
+
+    fn definition(&mut self) -> Result<(), ParseError> {
+        let position = self.parser.position();
+        let name = self.parser.ident()?;
+        if signature(&name).is_some() || self.definitions.contains_key(&name) {
+            return error_at(position, ErrorKind::Redefined(name));
+        }
+        let parameters = if self.parser.is_symbol("(") {
+            self.parser.pos += 1;
+            let parameters = self.parser.list(")", Parser::ident)?;
+            self.parser.symbol(")")?;
+            parameters
+        } else {
+            vec![]
+        };
+        let qubits = self.parser.list("{", Parser::ident)?;
+        self.parser.symbol("{")?;
+        let mut body = vec![];
+        while !self.parser.is_symbol("}") {
+            let position = self.parser.position();
+            let gate = self.parser.ident()?;
+            let call_parameters = self.parser.parameters()?;
+            let mut arguments = vec![];
+            for a in self.parser.list(";", Parser::argument)? {
+                match qubits.iter().position(|q| *q == a.name) {
+                    Some(q) if a.index.is_none() => arguments.push(q),
+                    _ => return error_at(a.position, ErrorKind::Undefined(a.name)),
+                }
+            }
+            self.parser.symbol(";")?;
+            if gate == "barrier" {
+                continue;
+            }
+            let (expected_parameters, expected_qubits) = match self.signature(&gate) {
+                Some(s) => s,
+                None => return error_at(position, ErrorKind::Undefined(gate)),
+            };
+            check_counts((expected_parameters, expected_qubits), call_parameters.len(), arguments.len(), position)?;
+            // Catches parameters that aren't declared.
+            let dummy = parameters.iter().map(|p| (p.clone(), 0.)).collect();
+            for e in &call_parameters {
+                e.eval(&dummy)?;
+            }
+            body.push(Call {
+                name: gate,
+                parameters: call_parameters,
+                qubits: arguments,
+                position: position,
+            });
+        }
+        self.parser.symbol("}")?;
+        self.definitions.insert(name, Definition {
+            parameters: parameters,
+            qubits: qubits.len(),
+            body: body,
+        });
+        Ok(())
+    }
+
+    fn measure(&mut self) -> Result<(), ParseError> {
+        let position = self.parser.position();
+        let qubit = self.parser.argument()?;
+        self.parser.symbol("->")?;
+        let bit = self.parser.argument()?;
+        self.parser.symbol(";")?;
+        let qubits = self.qubits(&qubit)?;
+        let (start, size) = match self.cregs.get(&bit.name) {
+            Some(&register) => register,
+            None => return error_at(bit.position, ErrorKind::Undefined(bit.name)),
+        };
+        match bit.index {
+            Some(i) if i >= size => return error_at(bit.position, ErrorKind::OutOfRange { index: i, size: size }),
+            Some(_) if qubits.len() != 1 => return error_at(position, ErrorKind::SizeMismatch),
+            None if qubits.len() != size || qubit.index.is_some() => return error_at(position, ErrorKind::SizeMismatch),
+            _ => {}
+        }
+        if start + bit.index.unwrap_or(0) != self.measured {
+            return error_at(position, ErrorKind::Unsupported("measurement out of bit order"));
+        }
+        for q in qubits {
+            let wire = match self.wires[q].take() {
+                Some(wire) => wire,
+                None => return error_at(position, ErrorKind::Measured),
+            };
+            let output = self.game.add(gate::Output::new(), T::default());
+            self.game.connect(wire, port(output, 0));
+            self.measured += 1;
+        }
+        Ok(())
+    }
+
+    /// Qubits an argument refers to.
+    fn qubits(&self, argument: &Argument) -> Result<Vec<usize>, ParseError> {
+        let (start, size) = match self.qregs.get(&argument.name) {
+            Some(&r) => r,
+            None => return error_at(argument.position, ErrorKind::Undefined(argument.name.clone())),
+        };
+        match argument.index {
+            Some(i) if i >= size => error_at(argument.position, ErrorKind::OutOfRange { index: i, size: size }),
+            Some(i) => Ok(vec![start + i]),
+            None => Ok((start..start + size).collect()),
+        }
+    }
+
+    fn signature(&self, name: &str) -> Option<(usize, usize)> {
+        signature(name).or_else(|| self.definitions.get(name).map(|d| (d.parameters.len(), d.qubits)))
+    }
+
+    fn apply(&mut self, name: &str, parameters: &[f64], qubits: &[usize], position: (usize, usize)) -> Result<(), ParseError> {
+        let expected = match self.signature(name) {
+            Some(s) => s,
+            None => return error_at(position, ErrorKind::Undefined(name.to_string())),
+        };
+        check_counts(expected, parameters.len(), qubits.len(), position)?;
+        if (1..qubits.len()).any(|i| qubits[..i].contains(&qubits[i])) {
+            return error_at(position, ErrorKind::RepeatedQubit);
+        }
+        if qubits.iter().any(|&q| self.wires[q].is_none()) {
+            return error_at(position, ErrorKind::Measured);
+        }
+        if signature(name).is_none() {
+            let calls = {
+                let definition = &self.definitions[name];
+                let values = definition.parameters.iter().cloned().zip(parameters.iter().cloned()).collect();
+                let mut calls = vec![];
+                for call in &definition.body {
+                    let parameters = call.parameters.iter().map(|e| e.eval(&values)).collect::<Result<Vec<_>, _>>()?;
+                    let targets = call.qubits.iter().map(|&q| qubits[q]).collect::<Vec<_>>();
+                    calls.push((call.name.clone(), parameters, targets, call.position));
+                }
+                calls
+            };
+            for (name, parameters, targets, position) in calls {
+                self.apply(&name, &parameters, &targets, position)?;
+            }
+            return Ok(());
+        }
+        if let Some(gate) = standard_gate(name, parameters) {
+            let node = self.game.add(gate, T::default());
+            for (i, &q) in qubits.iter().enumerate() {
+                self.game.connect(self.wires[q].unwrap(), port(node, i as u32));
+                self.wires[q] = Some(port(node, i as u32));
+            }
+        }
+        Ok(())
+    }
+}
+
+fn check_counts(expected: (usize, usize), parameters: usize, qubits: usize, position: (usize, usize)) -> Result<(), ParseError> {
+    if expected.0 != parameters {
+        error_at(position, ErrorKind::WrongParameterCount { expected: expected.0, found: parameters })
+    } else if expected.1 != qubits {
+        error_at(position, ErrorKind::WrongQubitCount { expected: expected.1, found: qubits })
+    } else {
+        Ok(())
+    }
+}
+
+/// Applications of a gate to registers: each register argument stands for all
+/// of its qubits in turn, and single qubits are repeated alongside them.
+fn broadcast(registers: &[Vec<usize>], position: (usize, usize)) -> Result<Vec<Vec<usize>>, ParseError> {
+    let size = registers.iter().map(|r| r.len()).max().unwrap_or(1);
+    if registers.iter().any(|r| r.len() != 1 && r.len() != size) {
+        return error_at(position, ErrorKind::SizeMismatch);
+    }
+    Ok((0..size).map(|i| registers.iter().map(|r| if r.len() == 1 { r[0] } else { r[i] }).collect()).collect())
+}
+
+/// Number of parameters and qubits of the gates in `qelib1.inc`.
+fn signature(name: &str) -> Option<(usize, usize)> {
+    Some(match name {
+        "id" | "x" | "y" | "z" | "h" | "s" | "sdg" | "t" | "tdg" | "sx" | "sxdg" => (0, 1),
+        "u1" | "p" | "u0" | "rx" | "ry" | "rz" => (1, 1),
+        "u2" => (2, 1),
+        "U" | "u3" | "u" => (3, 1),
+        "CX" | "cx" | "cy" | "cz" | "ch" | "csx" | "swap" => (0, 2),
+        "crx" | "cry" | "crz" | "cu1" | "cp" | "rxx" | "rzz" => (1, 2),
+        "cu3" => (3, 2),
+        "cu" => (4, 2),
+        "ccx" | "cswap" => (0, 3),
+        "c3x" => (0, 4),
+        "c4x" => (0, 5),
+        _ => return None,
+    })
+}
+
+/// Gate for a name that `signature` knows, or `None` for identities.
+fn standard_gate(name: &str, p: &[f64]) -> Option<Box<Gate>> {
+    let controlled = |controls, target| Some(gate::Controlled::new(controls, vec![], target));
+    match name {
+        "id" | "u0" => None,
+        "x" => Some(gate::Not::new()),
+        "y" => Some(gate::PauliY::new()),
+        "z" => Some(gate::PauliZ::new()),
+        "h" => Some(gate::Hadamard::new()),
+        "s" => Some(gate::S::new()),
+        "sdg" => Some(gate::SDagger::new()),
+        "t" => Some(gate::T::new()),
+        "tdg" => Some(gate::TDagger::new()),
+        "sx" => Some(gate::Sx::new()),
+        // Equal to the adjoint of SX up to global phase.
+        "sxdg" => Some(gate::Rx::new(-PI / 2.)),
+        "u1" | "p" => Some(gate::Phase::new(p[0])),
+        "rx" => Some(gate::Rx::new(p[0])),
+        "ry" => Some(gate::Ry::new(p[0])),
+        "rz" => Some(gate::Rz::new(p[0])),
+        "u2" => Some(gate::U3::new(PI / 2., p[0], p[1])),
+        "U" | "u3" | "u" => Some(gate::U3::new(p[0], p[1], p[2])),
+        "CX" | "cx" => Some(gate::ControlNot::new()),
+        "cy" => controlled(1, ::pauli_y()),
+        "cz" => controlled(1, ::pauli_z()),
+        "ch" => controlled(1, ::hadamard()),
+        "csx" => controlled(1, ::sqrt_not()),
+        "swap" => Some(gate::Swap::new()),
+        "crx" => controlled(1, ::rx(p[0])),
+        "cry" => controlled(1, ::ry(p[0])),
+        "crz" => controlled(1, ::rz(p[0])),
+        "cu1" | "cp" => controlled(1, ::phase(p[0])),
+        "cu3" => controlled(1, ::u3(p[0], p[1], p[2])),
+        "cu" => controlled(1, ::u3(p[0], p[1], p[2]) * Complex::from_polar(&1., &p[3])),
+        "rzz" => controlled(0, rzz(p[0])),
+        "rxx" => {
+            let h = kronecker_product(&[::hadamard(), ::hadamard()]);
+            controlled(0, h.clone() * rzz(p[0]) * h)
+        }
+        "ccx" => Some(gate::Toffoli::new()),
+        "cswap" => Some(gate::Fredkin::new()),
+        "c3x" => controlled(3, ::not()),
+        _ => controlled(4, ::not()),
+    }
+}
+
+/// exp(-iθ/2 Z⊗Z)
+fn rzz(theta: f64) -> DMatrix<Complex<f64>> {
+    let mut result = DMatrix::new_zeros(4, 4);
+    for i in 0..4 {
+        let parity = if i == 1 || i == 2 { 1. } else { -1. };
+        result[(i, i)] = Complex::from_polar(&1., &(parity * theta / 2.));
+    }
+    result
+}
+
+/// OpenQASM versions circuits can be written in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Version {
+    V2,
+    V3,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExportError {
+    /// The circuit can't be evaluated as it is.
+    Eval(EvalError),
+    /// The gate at this node can't be written in the chosen version.
+    Unsupported(NodeIndex),
+}
+
+impl<T> Game<T> {
+    /// Writes the circuit as an OpenQASM program.
+    ///
+    /// Qubits of register `q` are numbered by wire like in `Schedule`, and
+    /// gates come in topological order. Every `Output` measures its qubit into
+    /// register `c`, numbered by node index, after all of the gates. Inputs
+    /// with angles are prepared with `u3`, while gates without a standard
+    /// counterpart, such as custom ones, are `Unsupported`.
+    pub fn to_qasm(&self, version: Version) -> Result<String, ExportError> {
+        let schedule = match self.schedule() {
+            Ok(s) => s,
+            Err(EvalError::NoMatrix(node)) => return Err(ExportError::Unsupported(node)),
+            Err(e) => return Err(ExportError::Eval(e)),
+        };
+        let mut wires = HashMap::new();
+        let mut body = String::new();
+        for (q, p) in schedule.inputs().into_iter().enumerate() {
+            let node = p.node;
+            wires.insert(p, q);
+            match self.dag.node_weight(node).unwrap().process.kind() {
+                Kind::Input { theta, phi } => if theta != 0. {
+                    body.push_str(&call("u3", &[theta, phi, 0.], &[q]));
+                },
+                _ => return Err(ExportError::Unsupported(node)),
+            }
+        }
+        let mut iswap = false;
+        for node in self.dag.topological_order() {
+            let gate = &self.dag.node_weight(node).unwrap().process;
+            if gate.max_in() == 0 || gate.max_out() == 0 {
+                continue;
+            }
+            let mut qubits = vec![0; gate.max_in() as usize];
+            for (src, trg) in self.dag.parents(node) {
+                qubits[trg as usize] = wires.remove(&src).unwrap();
+            }
+            for (i, &q) in qubits.iter().enumerate() {
+                wires.insert(port(node, i as u32), q);
+            }
+            let kind = gate.kind();
+            iswap |= kind == Kind::ISwap;
+            match statements(&kind, &qubits, version) {
+                Some(s) => body.push_str(&s),
+                None => return Err(ExportError::Unsupported(node)),
+            }
+        }
+        let mut outputs = schedule.outputs().to_vec();
+        outputs.sort_by_key(|o| o.0);
+        for (bit, &(_, q)) in outputs.iter().enumerate() {
+            body.push_str(&match version {
+                Version::V2 => format!("measure q[{}] -> c[{}];\n", q, bit),
+                Version::V3 => format!("c[{}] = measure q[{}];\n", bit, q),
+            });
+        }
+
+        let mut result = match version {
+            Version::V2 => "OPENQASM 2.0;\ninclude \"qelib1.inc\";\n",
+            Version::V3 => "OPENQASM 3.0;\ninclude \"stdgates.inc\";\n",
+        }.to_string();
+        if iswap {
+            result.push_str("gate iswap a, b { s a; s b; h a; cx a, b; cx b, a; h b; }\n");
+        }
+        result.push_str(&match version {
+            Version::V2 => format!("qreg q[{}];\n", schedule.qubits()),
+            Version::V3 => format!("qubit[{}] q;\n", schedule.qubits()),
+        });
+        if !outputs.is_empty() {
+            result.push_str(&match version {
+                Version::V2 => format!("creg c[{}];\n", outputs.len()),
+                Version::V3 => format!("bit[{}] c;\n", outputs.len()),
+            });
+        }
+        result.push_str(&body);
+        Ok(result)
+    }
+}
+
+/// Gate statement, writing -0 as 0.
+fn call(name: &str, parameters: &[f64], qubits: &[usize]) -> String {
+    let mut result = name.to_string();
+    if !parameters.is_empty() {
+        result.push_str(&format!("({})", parameters.iter().map(|p| (p + 0.).to_string()).collect::<Vec<_>>().join(", ")));
+    }
+    let qubits = qubits.iter().map(|q| format!("q[{}]", q)).collect::<Vec<_>>();
+    result + " " + &qubits.join(", ") + ";\n"
+}
+
+/// Statements applying the gate of `kind` to `qubits`, if it can be written.
+fn statements(kind: &Kind, qubits: &[usize], version: Version) -> Option<String> {
+    let phase = match version {
+        Version::V2 => "u1",
+        Version::V3 => "p",
+    };
+    Some(match *kind {
+        Kind::Not => call("x", &[], qubits),
+        Kind::PauliY => call("y", &[], qubits),
+        Kind::PauliZ => call("z", &[], qubits),
+        Kind::Hadamard => call("h", &[], qubits),
+        Kind::S => call("s", &[], qubits),
+        Kind::SDagger => call("sdg", &[], qubits),
+        Kind::T => call("t", &[], qubits),
+        Kind::TDagger => call("tdg", &[], qubits),
+        Kind::Sx => call("sx", &[], qubits),
+        Kind::Rx(theta) => call("rx", &[theta], qubits),
+        Kind::Ry(theta) => call("ry", &[theta], qubits),
+        Kind::Rz(theta) => call("rz", &[theta], qubits),
+        Kind::Phase(phi) => call(phase, &[phi], qubits),
+        Kind::U3(theta, phi, lambda) => call("u3", &[theta, phi, lambda], qubits),
+        Kind::ControlNot => call("cx", &[], qubits),
+        Kind::Swap => call("swap", &[], qubits),
+        Kind::ISwap => call("iswap", &[], qubits),
+        Kind::Toffoli => call("ccx", &[], qubits),
+        Kind::Fredkin => call("cswap", &[], qubits),
+        Kind::Controlled { controls, ref negative, ref target } => {
+            if target.nrows() != 2 {
+                return None;
+            }
+            let controls = &qubits[..controls as usize];
+            // Negative controls trigger on 1 between a pair of X gates.
+            let flips = negative.iter().map(|&c| call("x", &[], &[qubits[c as usize]])).collect::<String>();
+            // target = e^(iα) u3(γ, β, δ) once α makes up for the phase of u3.
+            let (alpha, beta, gamma, delta) = zyz(target);
+            let (alpha, angles) = (alpha - (beta + delta) / 2., [gamma, beta, delta]);
+            let phased = (alpha / (2. * PI) - (alpha / (2. * PI)).round()).abs() > 1e-9;
+            let x = approx_eq(target, &::not(), TOLERANCE);
+            let gates = match (controls.len(), version) {
+                (0, _) => call("u3", &angles, qubits),
+                (1, _) if x => call("cx", &[], qubits),
+                (2, _) if x => call("ccx", &[], qubits),
+                (3, Version::V2) if x => call("c3x", &[], qubits),
+                (4, Version::V2) if x => call("c4x", &[], qubits),
+                (1, Version::V2) if phased => call("cu3", &angles, qubits) + &call(phase, &[alpha], controls),
+                (1, Version::V2) => call("cu3", &angles, qubits),
+                (1, Version::V3) => call("cu", &[gamma, beta, delta, alpha], qubits),
+                (_, Version::V2) => return None,
+                (n, Version::V3) if phased => {
+                    format!("ctrl({}) @ {}ctrl({}) @ {}", n, call("u3", &angles, qubits), n - 1, call(phase, &[alpha], controls))
+                }
+                (n, Version::V3) => format!("ctrl({}) @ {}", n, call("u3", &angles, qubits)),
+            };
+            flips.clone() + &gates + &flips
+        }
+        _ => return None,
+    })
+}
+
+#[test]
+fn from_qasm_test() {
+    use metrics::eq_up_to_phase;
+    use {control_not, apply_to_qubit, hadamard, rz, toffoli, swap};
+    let game = Game::<()>::from_qasm("OPENQASM 2.0;\n\
+        include \"qelib1.inc\";\n\
+        // Bell pair\n\
+        qreg q[2];\n\
+        creg c[2];\n\
+        h q[0];\n\
+        cx q[0], q[1];\n\
+        barrier q;\n\
+        measure q -> c;\n").unwrap();
+    assert_eq!(6, game.iter().count());
+    let expected = control_not(0, 1, 2) * apply_to_qubit(hadamard(), 0, 2);
+    assert!(eq_up_to_phase(&expected, &game.unitary().unwrap(), 0.000001));
+
+    let game = Game::<()>::from_qasm("OPENQASM 2.0;
+        gate rot(a, b) x, y { rz(a / 2) x; cx x, y; u1(-b*2+pi) y; }
+        qreg a[1]; qreg b[2];
+        rot(0.5, sin(pi/2)) a[0], b[1];
+        ccx b[1], a[0], b[0];
+        swap b[0], b[1];
+        id a;
+        measure a[0] -> c2[0];
+        creg c2[1];").map(|g| g.iter().count());
+    assert_eq!(Err(ParseError { line: 8, column: 25, kind: ErrorKind::Undefined("c2".to_string()) }), game);
+
+    let game = Game::<()>::from_qasm("OPENQASM 2.0;
+        gate rot(a, b) x, y { rz(a / 2) x; cx x, y; u1(-b*2+pi) y; }
+        qreg a[1]; qreg b[2];
+        rot(0.5, sin(pi/2)) a[0], b[1];
+        ccx b[1], a[0], b[0];
+        swap b[0], b[1];
+        id a;").unwrap();
+    assert_eq!(3 + 5 + 3, game.iter().count());
+    let rot = apply_to_qubit(::phase(PI - 2.), 1, 2) * control_not(0, 1, 2) * apply_to_qubit(rz(0.25), 0, 2);
+    let expected = swap(1, 2, 3) * toffoli(2, 0, 1, 3) * ::controlled(&[], &[], &rot, &[0, 2], 3);
+    assert!(eq_up_to_phase(&expected, &game.unitary().unwrap(), 0.000001));
+
+    let error = |source: &str| Game::<()>::from_qasm(source).map(|_| ()).unwrap_err();
+    assert_eq!(ParseError { line: 1, column: 10, kind: ErrorKind::UnsupportedVersion }, error("OPENQASM 3.0;"));
+    assert_eq!(ParseError { line: 2, column: 1, kind: ErrorKind::Undefined("foo".to_string()) },
+        error("OPENQASM 2.0; qreg q[1];\nfoo q[0];"));
+    assert_eq!(ParseError { line: 2, column: 3, kind: ErrorKind::OutOfRange { index: 1, size: 1 } },
+        error("OPENQASM 2.0; qreg q[1];\nx q[1];"));
+    assert_eq!(ParseError { line: 1, column: 37, kind: ErrorKind::RepeatedQubit },
+        error("OPENQASM 2.0; qreg q[2]; creg c[2]; cx q[1], q[1];"));
+    assert_eq!(ParseError { line: 2, column: 1, kind: ErrorKind::Measured },
+        error("OPENQASM 2.0; qreg q[1]; creg c[1]; measure q -> c;\nh q;"));
+    assert_eq!(ParseError { line: 1, column: 26, kind: ErrorKind::WrongParameterCount { expected: 1, found: 0 } },
+        error("OPENQASM 2.0; qreg q[1]; rx q;"));
+    assert_eq!(ParseError { line: 1, column: 37, kind: ErrorKind::SizeMismatch },
+        error("OPENQASM 2.0; qreg a[2]; qreg b[3]; cx a, b;"));
+    assert_eq!(ParseError { line: 1, column: 30, kind: ErrorKind::Expected(";") },
+        error("OPENQASM 2.0; qreg q[1]; h q h q;"));
+    assert_eq!(ParseError { line: 1, column: 29, kind: ErrorKind::UnexpectedEnd },
+        error("OPENQASM 2.0; qreg q[1]; h q"));
+    assert_eq!(ParseError { line: 1, column: 15, kind: ErrorKind::Unsupported("reset") },
+        error("OPENQASM 2.0; reset q;"));
+    // Outputs carry no bit, so measuring into any other order would swap bits.
+    assert_eq!(ParseError { line: 1, column: 45, kind: ErrorKind::Unsupported("measurement out of bit order") },
+        error("OPENQASM 2.0; qreg q[2]; creg c[2]; measure q[0] -> c[1]; measure q[1] -> c[0];"));
+    assert_eq!(ParseError { line: 2, column: 9, kind: ErrorKind::Unsupported("measurement out of bit order") },
+        error("OPENQASM 2.0; qreg q[2]; creg a[1]; creg b[1];\nmeasure q[1] -> b[0]; measure q[0] -> a[0];"));
+    assert!(Game::<()>::from_qasm("OPENQASM 2.0; qreg q[2]; creg a[1]; creg b[1];\n\
+        measure q[0] -> a[0]; measure q[1] -> b[0];").is_ok());
+    assert_eq!(ParseError { line: 1, column: 15, kind: ErrorKind::UnexpectedCharacter('#') },
+        error("OPENQASM 2.0; #"));
+}
+
+#[test]
+fn to_qasm_test() {
+    use super::gate::{Input, Output, Hadamard, ISwap, Controlled, Rz};
+    let bell = "OPENQASM 2.0;\ninclude \"qelib1.inc\";\nqreg q[2];\ncreg c[2];\nh q[0];\ncx q[0], q[1];\n\
+        measure q[0] -> c[0];\nmeasure q[1] -> c[1];\n";
+    let game = Game::<()>::from_qasm(bell).unwrap();
+    assert_eq!(Ok(bell.to_string()), game.to_qasm(Version::V2));
+    assert_eq!(Ok("OPENQASM 3.0;\ninclude \"stdgates.inc\";\nqubit[2] q;\nbit[2] c;\nh q[0];\ncx q[0], q[1];\n\
+        c[0] = measure q[0];\nc[1] = measure q[1];\n".to_string()), game.to_qasm(Version::V3));
+
+    let mut game = Game::new();
+    let inputs = (0..3).map(|_| game.add(Input::new(), ())).collect::<Vec<_>>();
+    let mut wires = inputs.iter().map(|&i| port(i, 0)).collect::<Vec<_>>();
+    let gates = vec![
+        (Hadamard::new(), vec![2]),
+        (ISwap::new(), vec![0, 2]),
+        (Controlled::new(1, vec![0], ::u3(0.3, 0.2, 0.1) * Complex::from_polar(&1., &0.4)), vec![1, 0]),
+        (Controlled::new(2, vec![1], ::not()), vec![2, 0, 1]),
+        (Rz::new(0.7), vec![1]),
+    ];
+    for (gate, qubits) in gates {
+        let node = game.add(gate, ());
+        for (i, &q) in qubits.iter().enumerate() {
+            game.connect(wires[q], port(node, i as u32));
+            wires[q] = port(node, i as u32);
+        }
+    }
+    for &w in &wires {
+        let output = game.add(Output::new(), ());
+        game.connect(w, port(output, 0));
+    }
+    let source = game.to_qasm(Version::V2).unwrap();
+    assert_eq!(Ok(::circuit::Equivalence::Equal), Game::<()>::from_qasm(&source).unwrap().equivalent(&game));
+    assert_eq!(Ok("OPENQASM 3.0;\ninclude \"stdgates.inc\";\n\
+        gate iswap a, b { s a; s b; h a; cx a, b; cx b, a; h b; }\n\
+        qubit[3] q;\nbit[3] c;\nh q[2];\niswap q[0], q[2];\nx q[1];\n\
+        cu(0.29999999999999993, 0.20000000000000018, 0.09999999999999996, 0.39999999999999997) q[1], q[0];\n\
+        x q[1];\nx q[0];\nccx q[2], q[0], q[1];\nx q[0];\nrz(0.7) q[1];\n\
+        c[0] = measure q[0];\nc[1] = measure q[1];\nc[2] = measure q[2];\n".to_string()), game.to_qasm(Version::V3));
+
+    let mut game = Game::new();
+    let a = game.add(Input::with_angles(0.5, 0.25), ());
+    let b = game.add(Input::new(), ());
+    let ry = game.add(Controlled::new(1, vec![], ::ry(0.3)), ());
+    game.connect(port(a, 0), port(ry, 0));
+    assert_eq!(Err(ExportError::Eval(EvalError::Unconnected(port(ry, 1)))), game.to_qasm(Version::V2));
+    game.connect(port(b, 0), port(ry, 1));
+    assert_eq!(Ok("OPENQASM 3.0;\ninclude \"stdgates.inc\";\nqubit[2] q;\nu3(0.5, 0.25, 0) q[0];\ncu(0.3, 0, 0, 0) q[0], q[1];\n".to_string()),
+        game.to_qasm(Version::V3));
+    let swap = game.add(Controlled::new(0, vec![], ::swap(0, 1, 2)), ());
+    game.connect(port(ry, 0), port(swap, 0));
+    game.connect(port(ry, 1), port(swap, 1));
+    assert_eq!(Err(ExportError::Unsupported(swap)), game.to_qasm(Version::V2));
+    let output = game.add(Output::new(), ());
+    game.connect(port(swap, 0), port(output, 2));
+    assert_eq!(Err(ExportError::Eval(EvalError::NoPort(port(output, 2)))), game.to_qasm(Version::V3));
+}
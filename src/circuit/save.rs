@@ -0,0 +1,316 @@
+use daggy::NodeIndex;
+use serde::{Serialize, Serializer, Deserialize, Deserializer};
+use serde::ser;
+use serde::de;
+use serde_json;
+use bincode;
+
+use std::collections::HashSet;
+
+use super::{Game, Port};
+use super::gate::{Kind, GateError, Controlled, validate};
+use metrics::TOLERANCE;
+
+/// Version of the format `Game` is saved in, stored at the start of every
+/// save so that older ones can still be told apart when the format changes.
+pub const FORMAT_VERSION: u32 = 1;
+
+#[derive(Debug)]
+pub enum SaveError {
+    Json(serde_json::Error),
+    Binary(bincode::Error),
+    /// The gate at this node is `Custom`, so there's no way to rebuild it.
+    Custom(NodeIndex),
+}
+
+#[derive(Debug)]
+pub enum LoadError {
+    Json(serde_json::Error),
+    Binary(bincode::Error),
+    /// The save is in a format version that this build doesn't read.
+    Version(u32),
+    /// The node at this index is `Custom`.
+    Custom(NodeIndex),
+    /// The gate at this node isn't valid, see `Game::try_add`.
+    InvalidGate(NodeIndex, GateError),
+    /// An edge uses this port, which its node doesn't have.
+    NoPort(Port<u32>),
+    /// More than one edge leads into this input port.
+    Taken(Port<u32>),
+    /// The edge from the first port to the second one would close a cycle.
+    Cycle(Port<u32>, Port<u32>),
+}
+
+#[derive(Deserialize)]
+struct Header {
+    version: u32,
+}
+
+#[derive(Serialize)]
+struct SaveRef<'a, T: 'a> {
+    version: u32,
+    nodes: Vec<NodeRef<'a, T>>,
+    edges: Vec<(Port<u32>, Port<u32>)>,
+}
+
+#[derive(Serialize)]
+struct NodeRef<'a, T: 'a> {
+    gate: Kind,
+    data: &'a T,
+}
+
+#[derive(Deserialize)]
+struct Save<T> {
+    version: u32,
+    nodes: Vec<SavedNode<T>>,
+    edges: Vec<(Port<u32>, Port<u32>)>,
+}
+
+#[derive(Deserialize)]
+struct SavedNode<T> {
+    gate: Kind,
+    data: T,
+}
+
+impl<T: Serialize> Game<T> {
+    /// Saves the circuit as JSON, keeping every gate with its parameters, the
+    /// data of its node and each edge with its port numbers.
+    pub fn to_json(&self) -> Result<String, SaveError> {
+        serde_json::to_string(&self.save_ref()?).map_err(SaveError::Json)
+    }
+
+    /// Saves the circuit like `to_json`, but in a compact binary form.
+    pub fn to_binary(&self) -> Result<Vec<u8>, SaveError> {
+        bincode::serialize(&self.save_ref()?).map_err(SaveError::Binary)
+    }
+
+    fn save_ref(&self) -> Result<SaveRef<T>, SaveError> {
+        let mut nodes = vec![];
+        for (i, n) in self.dag.raw_nodes().iter().enumerate() {
+            match n.weight.process.kind() {
+                Kind::Custom => return Err(SaveError::Custom(NodeIndex::new(i))),
+                kind => nodes.push(NodeRef {
+                    gate: kind,
+                    data: &n.weight.data,
+                }),
+            }
+        }
+        Ok(SaveRef {
+            version: FORMAT_VERSION,
+            nodes: nodes,
+            edges: self.iter_connections().collect(),
+        })
+    }
+}
+
+impl<T> Game<T> where for<'de> T: Deserialize<'de> {
+    /// Loads a circuit saved with `to_json`. Nodes and edges keep their indices.
+    pub fn from_json(json: &str) -> Result<Game<T>, LoadError> {
+        let header: Header = serde_json::from_str(json).map_err(LoadError::Json)?;
+        if header.version != FORMAT_VERSION {
+            return Err(LoadError::Version(header.version));
+        }
+        Game::load(serde_json::from_str(json).map_err(LoadError::Json)?)
+    }
+
+    /// Loads a circuit saved with `to_binary`.
+    pub fn from_binary(bytes: &[u8]) -> Result<Game<T>, LoadError> {
+        // The version is the first field, so it can be read on its own.
+        let version: u32 = bincode::deserialize(bytes).map_err(LoadError::Binary)?;
+        if version != FORMAT_VERSION {
+            return Err(LoadError::Version(version));
+        }
+        Game::load(bincode::deserialize(bytes).map_err(LoadError::Binary)?)
+    }
+}
+
+impl<T> Game<T> {
+    fn load(save: Save<T>) -> Result<Game<T>, LoadError> {
+        if save.version != FORMAT_VERSION {
+            return Err(LoadError::Version(save.version));
+        }
+        let mut game = Game::new();
+        for (i, node) in save.nodes.into_iter().enumerate() {
+            let gate = match node.gate {
+                // The fields come straight from the save, so check them before
+                // a huge or malformed target is ever turned into a matrix.
+                Kind::Controlled { controls, negative, target } => match Controlled::try_new(controls, negative, target) {
+                    Ok(g) => g,
+                    Err(e) => return Err(LoadError::InvalidGate(NodeIndex::new(i), e)),
+                },
+                kind => match kind.gate() {
+                    Some(g) => g,
+                    None => return Err(LoadError::Custom(NodeIndex::new(i))),
+                },
+            };
+            if let Err(e) = validate(&*gate, TOLERANCE) {
+                return Err(LoadError::InvalidGate(NodeIndex::new(i), e));
+            }
+            game.add(gate, node.data);
+        }
+        let mut taken = HashSet::new();
+        for (src, trg) in save.edges {
+            let ports = |p: Port<u32>| game.get(p.node).map(|n| (n.0.max_in(), n.0.max_out()));
+            match ports(src) {
+                Some((_, outputs)) if src.port < outputs => {}
+                _ => return Err(LoadError::NoPort(src)),
+            }
+            match ports(trg) {
+                Some((inputs, _)) if trg.port < inputs => {}
+                _ => return Err(LoadError::NoPort(trg)),
+            }
+            // `connect` would silently replace the earlier edge.
+            if !taken.insert(trg) {
+                return Err(LoadError::Taken(trg));
+            }
+            if !game.connect(src, trg) {
+                return Err(LoadError::Cycle(src, trg));
+            }
+        }
+        Ok(game)
+    }
+}
+
+/// Saved like `Game::to_json` does, failing on `Custom` gates.
+impl<T: Serialize> Serialize for Game<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self.save_ref() {
+            Ok(save) => save.serialize(serializer),
+            Err(e) => Err(ser::Error::custom(format!("{:?}", e))),
+        }
+    }
+}
+
+/// Loaded and validated like `Game::from_json` does.
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for Game<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Game<T>, D::Error> {
+        Game::load(Save::deserialize(deserializer)?).map_err(|e| de::Error::custom(format!("{:?}", e)))
+    }
+}
+
+#[test]
+fn save_test() {
+    use nalgebra::{DMatrix, Eye};
+    use num::Complex;
+    use super::port;
+    use super::gate::{Input, Output, Hadamard, ControlNot, Controlled};
+    let mut game = Game::new();
+    let a = game.add(Input::with_angles(0.5, 0.25), "a".to_string());
+    let b = game.add(Input::new(), "b".to_string());
+    let h = game.add(Hadamard::new(), "h".to_string());
+    let cnot = game.add(ControlNot::new(), "cnot".to_string());
+    let rz = game.add(Controlled::new(1, vec![0], ::rz(0.3)), "rz".to_string());
+    let out_a = game.add(Output::new(), "out".to_string());
+    let out_b = game.add(Output::new(), "out".to_string());
+    game.connect(port(a, 0), port(h, 0));
+    game.connect(port(h, 0), port(cnot, 1));
+    game.connect(port(b, 0), port(cnot, 0));
+    game.connect(port(cnot, 1), port(rz, 0));
+    game.connect(port(cnot, 0), port(rz, 1));
+    game.connect(port(rz, 0), port(out_a, 0));
+    game.connect(port(rz, 1), port(out_b, 0));
+    let same = |loaded: &Game<String>| {
+        assert!(game.iter().zip(loaded.iter()).all(|(a, b)| a.0.kind() == b.0.kind() && a.1 == b.1));
+        assert_eq!(game.iter().count(), loaded.iter().count());
+        assert_eq!(game.iter_connections().collect::<Vec<_>>(), loaded.iter_connections().collect::<Vec<_>>());
+    };
+    let json = game.to_json().unwrap();
+    same(&Game::from_json(&json).unwrap());
+    same(&Game::from_binary(&game.to_binary().unwrap()).unwrap());
+    same(&serde_json::from_str(&serde_json::to_string(&game).unwrap()).unwrap());
+
+    let mut value: serde_json::Value = serde_json::from_str(&json).unwrap();
+    value["version"] = serde_json::Value::from(2);
+    match Game::<String>::from_json(&value.to_string()) {
+        Err(LoadError::Version(2)) => {}
+        r => panic!("{:?}", r.map(|_| ())),
+    }
+
+    // The broken saves below are built directly, so they don't depend on how
+    // the JSON happens to be laid out.
+    let saved = || Save {
+        version: FORMAT_VERSION,
+        nodes: game.iter().map(|(g, d)| SavedNode { gate: g.kind(), data: d.clone() }).collect(),
+        edges: game.iter_connections().collect(),
+    };
+    let load = |save: Save<String>| Game::load(save).map(|_| ());
+    let edge = |save: &Save<String>, trg: Port<u32>| save.edges.iter().position(|e| e.1 == trg).unwrap();
+    let controlled = |controls: u32, negative: Vec<u32>, target: DMatrix<Complex<f64>>| Kind::Controlled {
+        controls: controls,
+        negative: negative,
+        target: target,
+    };
+
+    let mut save = saved();
+    save.version = 2;
+    match load(save) {
+        Err(LoadError::Version(2)) => {}
+        r => panic!("{:?}", r),
+    }
+
+    let mut save = saved();
+    let i = edge(&save, port(out_b, 0));
+    save.edges[i] = (port(rz, 1), port(out_a, 0));
+    match load(save) {
+        Err(LoadError::Taken(p)) if p == port(out_a, 0) => {}
+        r => panic!("{:?}", r),
+    }
+
+    let mut save = saved();
+    let i = edge(&save, port(out_b, 0));
+    save.edges[i] = (port(rz, 2), port(out_b, 0));
+    match load(save) {
+        Err(LoadError::NoPort(p)) if p == port(rz, 2) => {}
+        r => panic!("{:?}", r),
+    }
+
+    let mut save = saved();
+    let i = edge(&save, port(h, 0));
+    save.edges[i] = (port(rz, 0), port(h, 0));
+    match load(save) {
+        Err(LoadError::Cycle(..)) => {}
+        r => panic!("{:?}", r),
+    }
+
+    let mut save = saved();
+    let mut target = ::rz(0.3);
+    target[(0, 1)] = Complex::new(0.5, 0.);
+    save.nodes[rz.index()].gate = controlled(1, vec![0], target);
+    match load(save) {
+        Err(LoadError::InvalidGate(n, GateError::NotUnitary { .. })) if n == rz => {}
+        r => panic!("{:?}", r),
+    }
+
+    let mut save = saved();
+    save.nodes[rz.index()].gate = controlled(1, vec![0], DMatrix::new_identity(3));
+    match load(save) {
+        Err(LoadError::InvalidGate(n, GateError::WrongDimension { size: 3, .. })) if n == rz => {}
+        r => panic!("{:?}", r),
+    }
+
+    let mut save = saved();
+    save.nodes[rz.index()].gate = controlled(1, vec![1], ::rz(0.3));
+    match load(save) {
+        Err(LoadError::InvalidGate(n, GateError::NotControl { port: 1, controls: 1 })) if n == rz => {}
+        r => panic!("{:?}", r),
+    }
+
+    let mut save = saved();
+    save.nodes[rz.index()].gate = controlled(40, vec![0], ::rz(0.3));
+    match load(save) {
+        Err(LoadError::InvalidGate(n, GateError::TooManyPorts { ports: 41 })) if n == rz => {}
+        r => panic!("{:?}", r),
+    }
+
+    struct Custom;
+    impl super::gate::Gate for Custom {
+        fn max_in(&self) -> u32 { 0 }
+        fn max_out(&self) -> u32 { 0 }
+        fn process(&self, _: &[::nalgebra::DMatrix<::num::Complex<f64>>]) -> Vec<::nalgebra::DMatrix<::num::Complex<f64>>> { vec![] }
+    }
+    let custom = game.add(Box::new(Custom), "custom".to_string());
+    match game.to_json() {
+        Err(SaveError::Custom(n)) if n == custom => {}
+        r => panic!("{:?}", r),
+    }
+}
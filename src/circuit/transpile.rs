@@ -0,0 +1,384 @@
+use nalgebra::DMatrix;
+use num::Complex;
+use daggy::NodeIndex;
+
+use std::f64::consts::PI;
+
+use super::{Game, Port, port};
+use super::gate::{self, Gate, GateError, Kind, validate};
+use metrics::{approx_eq, TOLERANCE};
+use {adjoint, hadamard, not, phase, rz, t, t_dagger};
+
+/// Gate sets a circuit can be rewritten into.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Basis {
+    /// `Hadamard`, `T` and `ControlNot`. Only gates whose rotations are
+    /// multiples of π/4 can be written exactly with these.
+    HTCnot,
+    /// `Rz`, `Sx` and `ControlNot`, which can write any gate.
+    RzSxCnot,
+}
+
+impl Basis {
+    fn contains(&self, kind: &Kind) -> bool {
+        match (*self, kind) {
+            (_, &Kind::ControlNot) => true,
+            (Basis::HTCnot, &Kind::Hadamard) | (Basis::HTCnot, &Kind::T) => true,
+            (Basis::RzSxCnot, &Kind::Rz(_)) | (Basis::RzSxCnot, &Kind::Sx) => true,
+            _ => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TranspileError {
+    /// The gate at this node fails `gate::validate`.
+    Invalid(NodeIndex, GateError),
+    /// The gate at this node can't be written in the basis, either because it
+    /// has no matrix or because the basis can't express it exactly.
+    Unsupported(NodeIndex),
+    /// An edge uses this port, which its gate doesn't have.
+    NoPort(Port<u32>),
+}
+
+/// Step of a decomposition, acting on the ports of the gate being decomposed.
+enum Op {
+    Single(DMatrix<Complex<f64>>, usize),
+    Cnot(usize, usize),
+}
+
+impl<T: Clone> Game<T> {
+    /// Rewrites the circuit so that every gate with both inputs and outputs is
+    /// in `basis`. Gates of the basis and gates without inputs or outputs, like
+    /// `Input` and `Output`, are kept. The gates replacing a gate get copies of
+    /// its data, and the result equals the original up to global phase.
+    ///
+    /// The circuit is left untouched when some gate can't be rewritten. Like
+    /// `remove`, this can move nodes to other indices.
+    pub fn transpile(&mut self, basis: Basis) -> Result<(), TranspileError> {
+        if let Some(p) = self.missing_port() {
+            return Err(TranspileError::NoPort(p));
+        }
+        let mut plan = vec![];
+        for (i, node) in self.dag.raw_nodes().iter().enumerate() {
+            let index = NodeIndex::new(i);
+            let gate = &*node.weight.process;
+            if let Err(e) = validate(gate, TOLERANCE) {
+                return Err(TranspileError::Invalid(index, e));
+            }
+            let kind = gate.kind();
+            if gate.max_in() == 0 || gate.max_out() == 0 || basis.contains(&kind) {
+                continue;
+            }
+            let mut gates = vec![];
+            for op in decompose(gate, &kind).ok_or(TranspileError::Unsupported(index))? {
+                match op {
+                    Op::Single(u, p) => {
+                        for g in synthesize(&u, basis).ok_or(TranspileError::Unsupported(index))? {
+                            gates.push((g, vec![p]));
+                        }
+                    }
+                    Op::Cnot(c, t) => gates.push((gate::ControlNot::new(), vec![c, t])),
+                }
+            }
+            plan.push((index, gates));
+        }
+        let replaced = plan.iter().map(|p| p.0).collect::<Vec<_>>();
+        for (node, gates) in plan {
+            self.replace(node, gates);
+        }
+        for node in replaced.into_iter().rev() {
+            self.remove(&node);
+        }
+        Ok(())
+    }
+
+    /// Wires `gates`, given with the ports of `node` they act on, in place of
+    /// `node` without removing it.
+    fn replace(&mut self, node: NodeIndex, gates: Vec<(Box<Gate>, Vec<usize>)>) {
+        let data = self.dag.node_weight(node).unwrap().data.clone();
+        let ports = self.dag.node_weight(node).unwrap().process.max_in() as usize;
+        let mut current: Vec<Option<Port<u32>>> = vec![None; ports];
+        for (src, trg) in self.dag.parents(node) {
+            current[trg as usize] = Some(src);
+        }
+        for (gate, targets) in gates {
+            let new = self.add(gate, data.clone());
+            for (i, &p) in targets.iter().enumerate() {
+                if let Some(src) = current[p] {
+                    self.connect(src, port(new, i as u32));
+                }
+                current[p] = Some(port(new, i as u32));
+            }
+        }
+        let children = self.dag.children(node).collect::<Vec<_>>();
+        for (src, trg) in children {
+            if let Some(src) = current[src as usize] {
+                self.connect(src, trg);
+            }
+        }
+    }
+}
+
+fn decompose(gate: &Gate, kind: &Kind) -> Option<Vec<Op>> {
+    let mut ops = vec![];
+    match *kind {
+        Kind::ControlNot => ops.push(Op::Cnot(0, 1)),
+        Kind::Swap => swap(0, 1, &mut ops),
+        Kind::ISwap => {
+            ops.push(Op::Single(::s(), 0));
+            ops.push(Op::Single(::s(), 1));
+            ops.push(Op::Single(hadamard(), 1));
+            ops.push(Op::Cnot(0, 1));
+            ops.push(Op::Single(hadamard(), 1));
+            swap(0, 1, &mut ops);
+        }
+        Kind::Toffoli => toffoli(0, 1, 2, &mut ops),
+        Kind::Fredkin => {
+            ops.push(Op::Cnot(2, 1));
+            toffoli(0, 1, 2, &mut ops);
+            ops.push(Op::Cnot(2, 1));
+        }
+        Kind::Controlled { controls, ref negative, ref target } => {
+            if target.nrows() != 2 {
+                return None;
+            }
+            for &n in negative {
+                ops.push(Op::Single(not(), n as usize));
+            }
+            let controls = (0..controls as usize).collect::<Vec<_>>();
+            controlled(&controls, target, controls.len(), &mut ops);
+            for &n in negative {
+                ops.push(Op::Single(not(), n as usize));
+            }
+        }
+        _ => match gate.matrix() {
+            Some(ref m) if m.nrows() == 2 => ops.push(Op::Single(m.clone(), 0)),
+            _ => return None,
+        },
+    }
+    Some(ops)
+}
+
+fn swap(a: usize, b: usize, ops: &mut Vec<Op>) {
+    ops.push(Op::Cnot(a, b));
+    ops.push(Op::Cnot(b, a));
+    ops.push(Op::Cnot(a, b));
+}
+
+/// The usual circuit of six CNOTs and seven T gates.
+fn toffoli(a: usize, b: usize, target: usize, ops: &mut Vec<Op>) {
+    ops.push(Op::Single(hadamard(), target));
+    ops.push(Op::Cnot(b, target));
+    ops.push(Op::Single(t_dagger(), target));
+    ops.push(Op::Cnot(a, target));
+    ops.push(Op::Single(t(), target));
+    ops.push(Op::Cnot(b, target));
+    ops.push(Op::Single(t_dagger(), target));
+    ops.push(Op::Cnot(a, target));
+    ops.push(Op::Single(t(), b));
+    ops.push(Op::Single(t(), target));
+    ops.push(Op::Single(hadamard(), target));
+    ops.push(Op::Cnot(a, b));
+    ops.push(Op::Single(t(), a));
+    ops.push(Op::Single(t_dagger(), b));
+    ops.push(Op::Cnot(a, b));
+}
+
+/// Single qubit `u` on `target` controlled by all of `controls`.
+///
+/// More than one control is reduced to fewer with lemma 7.5 of Barenco et al.,
+/// "Elementary gates for quantum computation", which needs a square root of `u`.
+fn controlled(controls: &[usize], u: &DMatrix<Complex<f64>>, target: usize, ops: &mut Vec<Op>) {
+    let x = approx_eq(u, &not(), TOLERANCE);
+    match controls.len() {
+        0 => ops.push(Op::Single(u.clone(), target)),
+        1 if x => ops.push(Op::Cnot(controls[0], target)),
+        1 => {
+            // Nielsen and Chuang, corollary 4.2: u = e^(iα) A X B X C with ABC = I.
+            let (alpha, beta, gamma, delta) = zyz(u);
+            ops.push(Op::Single(rz((delta - beta) / 2.), target));
+            ops.push(Op::Cnot(controls[0], target));
+            ops.push(Op::Single(::ry(-gamma / 2.) * rz(-(delta + beta) / 2.), target));
+            ops.push(Op::Cnot(controls[0], target));
+            ops.push(Op::Single(rz(beta) * ::ry(gamma / 2.), target));
+            ops.push(Op::Single(phase(alpha), controls[0]));
+        }
+        2 if x => toffoli(controls[0], controls[1], target, ops),
+        n => {
+            let v = sqrt(u);
+            let (rest, last) = (&controls[..n - 1], controls[n - 1]);
+            controlled(&[last], &v, target, ops);
+            controlled(rest, &not(), last, ops);
+            controlled(&[last], &adjoint(&v), target, ops);
+            controlled(rest, &not(), last, ops);
+            controlled(rest, &v, target, ops);
+        }
+    }
+}
+
+/// Writes the single qubit `u` with gates of `basis`, up to global phase.
+fn synthesize(u: &DMatrix<Complex<f64>>, basis: Basis) -> Option<Vec<Box<Gate>>> {
+    let (_, beta, gamma, delta) = zyz(u);
+    let mut result = vec![];
+    match basis {
+        Basis::RzSxCnot => {
+            // Rz(β) Ry(γ) Rz(δ) = Rz(β + π) SX Rz(γ + π) SX Rz(δ) up to phase.
+            let rotate = |theta: f64, result: &mut Vec<Box<Gate>>| {
+                if multiple(theta, 2. * PI).is_none() {
+                    result.push(gate::Rz::new(normalize(theta)));
+                }
+            };
+            if gamma.abs() < 1e-9 {
+                rotate(beta + delta, &mut result);
+            } else {
+                rotate(delta, &mut result);
+                result.push(gate::Sx::new());
+                rotate(gamma + PI, &mut result);
+                result.push(gate::Sx::new());
+                rotate(beta + PI, &mut result);
+            }
+        }
+        Basis::HTCnot => {
+            // Rz(kπ/4) is T^k up to phase, and Ry(γ) = S H Rz(γ) H S† with S = T².
+            let eighths = |theta| multiple(theta, PI / 4.).map(|k| ((k % 8 + 8) % 8) as usize);
+            let (b, g, d) = match (eighths(beta), eighths(gamma), eighths(delta)) {
+                (Some(b), Some(g), Some(d)) => (b, g, d),
+                _ => return None,
+            };
+            let ts = |k: usize, result: &mut Vec<Box<Gate>>| {
+                for _ in 0..k % 8 {
+                    result.push(gate::T::new());
+                }
+            };
+            if g == 0 {
+                ts(b + d, &mut result);
+            } else {
+                ts(d + 6, &mut result);
+                result.push(gate::Hadamard::new());
+                ts(g, &mut result);
+                result.push(gate::Hadamard::new());
+                ts(b + 2, &mut result);
+            }
+        }
+    }
+    Some(result)
+}
+
+/// Splits a single qubit unitary into e^(iα) Rz(β) Ry(γ) Rz(δ), returning
+/// (α, β, γ, δ). When γ is 0 or π the whole z rotation is put in β.
+pub fn zyz(u: &DMatrix<Complex<f64>>) -> (f64, f64, f64, f64) {
+    let det = u[(0, 0)] * u[(1, 1)] - u[(0, 1)] * u[(1, 0)];
+    let alpha = det.arg() / 2.;
+    let global = Complex::from_polar(&1., &-alpha);
+    let (a, b) = (u[(0, 0)] * global, u[(1, 0)] * global);
+    let gamma = 2. * b.norm().atan2(a.norm());
+    let (beta, delta) = if b.norm() < 1e-9 {
+        (-2. * a.arg(), 0.)
+    } else if a.norm() < 1e-9 {
+        (2. * b.arg(), 0.)
+    } else {
+        (b.arg() - a.arg(), -a.arg() - b.arg())
+    };
+    (alpha, beta, gamma, delta)
+}
+
+/// A square root of a single qubit unitary.
+fn sqrt(u: &DMatrix<Complex<f64>>) -> DMatrix<Complex<f64>> {
+    let det = u[(0, 0)] * u[(1, 1)] - u[(0, 1)] * u[(1, 0)];
+    let trace = u[(0, 0)] + u[(1, 1)];
+    let mut s = det.sqrt();
+    if (trace + s * 2.).norm() < 1e-9 {
+        s = -s;
+    }
+    let mut result = u.clone();
+    result[(0, 0)] = result[(0, 0)] + s;
+    result[(1, 1)] = result[(1, 1)] + s;
+    result * (Complex::new(1., 0.) / (trace + s * 2.).sqrt())
+}
+
+/// `x / unit` when it is an integer.
+fn multiple(x: f64, unit: f64) -> Option<i64> {
+    let k = x / unit;
+    if (k - k.round()).abs() < 1e-9 {
+        Some(k.round() as i64)
+    } else {
+        None
+    }
+}
+
+/// Angle in (-π, π].
+fn normalize(theta: f64) -> f64 {
+    let theta = theta % (2. * PI);
+    if theta > PI {
+        theta - 2. * PI
+    } else if theta <= -PI {
+        theta + 2. * PI
+    } else {
+        theta
+    }
+}
+
+#[test]
+fn synthesize_test() {
+    use metrics::eq_up_to_phase;
+    use nalgebra::Eye;
+    use {u3, sqrt_not, pauli_y};
+    let product = |gates: Vec<Box<Gate>>| gates.into_iter()
+        .fold(DMatrix::new_identity(2), |acc: DMatrix<Complex<f64>>, g| g.matrix().unwrap() * acc);
+    for u in vec![u3(0.3, 1.1, -2.5), u3(PI, 0.2, 0.), rz(0.7), hadamard(), pauli_y(), sqrt_not()] {
+        let (alpha, beta, gamma, delta) = zyz(&u);
+        let expected = rz(beta) * ::ry(gamma) * rz(delta) * Complex::from_polar(&1., &alpha);
+        assert!(approx_eq(&u, &expected, TOLERANCE));
+        assert!(eq_up_to_phase(&u, &product(synthesize(&u, Basis::RzSxCnot).unwrap()), 0.000001));
+        let root = sqrt(&u);
+        assert!(approx_eq(&u, &(root.clone() * root), TOLERANCE));
+    }
+    for u in vec![hadamard(), pauli_y(), t_dagger(), ::s(), sqrt_not(), u3(PI / 2., PI / 4., -PI / 4.)] {
+        assert!(eq_up_to_phase(&u, &product(synthesize(&u, Basis::HTCnot).unwrap()), 0.000001));
+    }
+    assert!(synthesize(&rz(0.7), Basis::HTCnot).is_none());
+}
+
+#[test]
+fn transpile_test() {
+    use metrics::eq_up_to_phase;
+    use super::build;
+    use super::gate::{PauliY, PauliZ, S, U3, Rx, Swap, ISwap, Toffoli, Fredkin, Controlled};
+    let clifford_t = || vec![
+        (PauliY::new(), vec![0]),
+        (PauliZ::new(), vec![1]),
+        (S::new(), vec![2]),
+        (U3::new(PI / 2., PI / 4., 0.), vec![3]),
+        (Swap::new(), vec![0, 2]),
+        (ISwap::new(), vec![1, 3]),
+        (Toffoli::new(), vec![3, 0, 1]),
+        (Fredkin::new(), vec![2, 3, 0]),
+        (Controlled::new(2, vec![1], ::pauli_z()), vec![0, 1, 3]),
+    ];
+    for &basis in &[Basis::HTCnot, Basis::RzSxCnot] {
+        let mut game = build(4, clifford_t());
+        let before = game.unitary().unwrap();
+        assert_eq!(Ok(()), game.transpile(basis));
+        assert!(game.iter().all(|(g, _)| g.max_in() == 0 || g.max_out() == 0 || basis.contains(&g.kind())));
+        assert!(eq_up_to_phase(&before, &game.unitary().unwrap(), 0.000001));
+    }
+
+    let mut game = build(4, vec![
+        (Rx::new(0.3), vec![1]),
+        (Controlled::new(3, vec![0], ::hadamard()), vec![0, 1, 2, 3]),
+        (Controlled::new(1, vec![], ::u3(0.4, 0.5, 0.6)), vec![3, 2]),
+    ]);
+    let before = game.unitary().unwrap();
+    assert_eq!(Err(TranspileError::Unsupported(NodeIndex::new(4))), game.transpile(Basis::HTCnot));
+    assert_eq!(11, game.iter().count());
+    assert_eq!(Ok(()), game.transpile(Basis::RzSxCnot));
+    assert!(game.iter().all(|(g, d)| *d != 0 || g.max_in() == 0 || g.max_out() == 0));
+    assert!(eq_up_to_phase(&before, &game.unitary().unwrap(), 0.000001));
+
+    let mut game = build(4, vec![(Rx::new(0.3), vec![1])]);
+    let (rx, output) = (NodeIndex::new(4), NodeIndex::new(5));
+    game.connect(port(rx, 0), port(output, 3));
+    assert_eq!(Err(TranspileError::NoPort(port(output, 3))), game.transpile(Basis::RzSxCnot));
+    assert_eq!(9, game.iter().count());
+}
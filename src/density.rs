@@ -0,0 +1,311 @@
+use nalgebra::DMatrix;
+use num::{Complex, One, Zero};
+use rand::Rng;
+
+use std::ops::Index;
+
+use state::StateVector;
+use adjoint;
+use measure::{outcome_of, outcome_bits, choose, sample, Histogram};
+
+/// Mixed state of a register stored as its 2^n x 2^n density matrix.
+///
+/// The matrix is kept vectorized as a 2n qubit `StateVector`, row index in the
+/// upper n qubits and column index in the lower n qubits. Conjugating by a gate
+/// is then the gate on the row qubits followed by its complex conjugate on the
+/// column qubits, and it is done in place without building a full unitary.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DensityMatrix {
+    qubits: usize,
+    rho: StateVector,
+}
+
+impl DensityMatrix {
+    /// Register of `qubits` qubits in the state |0...0><0...0|.
+    pub fn new(qubits: usize) -> DensityMatrix {
+        DensityMatrix {
+            qubits: qubits,
+            rho: StateVector::new(2 * qubits),
+        }
+    }
+
+    /// Takes either a state vector or a density matrix in the crate's `DMatrix` layout.
+    pub fn from_mat(state: &DMatrix<Complex<f64>>) -> DensityMatrix {
+        if state.ncols() == 1 {
+            return DensityMatrix::from_state_vector(&StateVector::from_mat(state));
+        }
+        assert_eq!(state.nrows(), state.ncols());
+        let dim = state.nrows();
+        let mut rho = Vec::with_capacity(dim * dim);
+        for r in 0..dim {
+            for c in 0..dim {
+                rho.push(state[(r, c)]);
+            }
+        }
+        let rho = StateVector::from_amplitudes(rho);
+        DensityMatrix {
+            qubits: rho.qubits() / 2,
+            rho: rho,
+        }
+    }
+
+    pub fn from_state_vector(state: &StateVector) -> DensityMatrix {
+        let amplitudes = state.amplitudes();
+        let mut rho = Vec::with_capacity(amplitudes.len() * amplitudes.len());
+        for a in amplitudes {
+            for b in amplitudes {
+                rho.push(a * b.conj());
+            }
+        }
+        DensityMatrix {
+            qubits: state.qubits(),
+            rho: StateVector::from_amplitudes(rho),
+        }
+    }
+
+    pub fn mat(&self) -> DMatrix<Complex<f64>> {
+        let dim = 1 << self.qubits;
+        let mut result = DMatrix::new_zeros(dim, dim);
+        for r in 0..dim {
+            for c in 0..dim {
+                result[(r, c)] = self[(r, c)];
+            }
+        }
+        result
+    }
+
+    pub fn qubits(&self) -> usize {
+        self.qubits
+    }
+
+    pub fn trace(&self) -> Complex<f64> {
+        (0..1 << self.qubits).fold(Complex::zero(), |a, i| a + self[(i, i)])
+    }
+
+    /// Tr(ρ²), which is 1 for pure states and drops towards 1/2^n as the state decoheres.
+    pub fn purity(&self) -> f64 {
+        self.rho.amplitudes().iter().map(|a| a.norm_sqr()).sum()
+    }
+
+    /// Probability of every basis state, in the order of `basis_n`.
+    pub fn probabilities(&self) -> Vec<f64> {
+        (0..1 << self.qubits).map(|i| self[(i, i)].re).collect()
+    }
+
+    /// Measures `qubit` in the computational basis and collapses the state.
+    pub fn measure<R: Rng>(&mut self, qubit: usize, rng: &mut R) -> usize {
+        self.measure_qubits(&[qubit], rng)[0]
+    }
+
+    /// Measures `targets` in the computational basis and collapses the state.
+    ///
+    /// Returns one bit per target in the same order as `targets`.
+    pub fn measure_qubits<R: Rng>(&mut self, targets: &[usize], rng: &mut R) -> Vec<usize> {
+        let mut probabilities = vec![0.; 1 << targets.len()];
+        for (i, p) in self.probabilities().into_iter().enumerate() {
+            probabilities[outcome_of(i, self.qubits, targets)] += p;
+        }
+        let outcome = choose(&probabilities, rng);
+        let norm = Complex::new(probabilities[outcome], 0.);
+        let dim = 1 << self.qubits;
+        let mut rho = Vec::with_capacity(dim * dim);
+        for r in 0..dim {
+            for c in 0..dim {
+                if outcome_of(r, self.qubits, targets) == outcome && outcome_of(c, self.qubits, targets) == outcome {
+                    rho.push(self[(r, c)] / norm);
+                } else {
+                    rho.push(Complex::zero());
+                }
+            }
+        }
+        self.rho = StateVector::from_amplitudes(rho);
+        outcome_bits(outcome, targets.len())
+    }
+
+    /// Measures every qubit `shots` times without disturbing the state.
+    pub fn sample<R: Rng>(&self, shots: usize, rng: &mut R) -> Histogram {
+        let targets: Vec<_> = (0..self.qubits).collect();
+        self.sample_qubits(&targets, shots, rng)
+    }
+
+    /// Measures the `targets` qubits `shots` times without disturbing the state.
+    pub fn sample_qubits<R: Rng>(&self, targets: &[usize], shots: usize, rng: &mut R) -> Histogram {
+        sample(&self.probabilities(), self.qubits, targets, shots, rng)
+    }
+
+    /// Applies the unitary `gate` to the `targets` qubits, ordered as in `StateVector::apply`.
+    pub fn apply(&mut self, gate: &DMatrix<Complex<f64>>, targets: &[usize]) {
+        let (rows, cols) = self.halves(targets);
+        self.rho.apply(gate, &rows);
+        self.rho.apply(&conjugate(gate), &cols);
+    }
+
+    /// Applies the channel given by the Kraus operators `kraus` to the `targets` qubits.
+    pub fn apply_channel(&mut self, kraus: &[DMatrix<Complex<f64>>], targets: &[usize]) {
+        let (rows, cols) = self.halves(targets);
+        let mut result = vec![Complex::zero(); self.rho.amplitudes().len()];
+        for k in kraus {
+            let mut term = self.rho.clone();
+            term.apply(k, &rows);
+            term.apply(&conjugate(k), &cols);
+            for (r, t) in result.iter_mut().zip(term.amplitudes()) {
+                *r = *r + t;
+            }
+        }
+        self.rho = StateVector::from_amplitudes(result);
+    }
+
+    /// Reduced state of the `keep` qubits, the first of them becoming qubit 0.
+    pub fn partial_trace(&self, keep: &[usize]) -> DensityMatrix {
+        DensityMatrix::from_mat(&partial_trace(&self.mat(), keep))
+    }
+
+    fn halves(&self, targets: &[usize]) -> (Vec<usize>, Vec<usize>) {
+        (targets.to_vec(), targets.iter().map(|t| t + self.qubits).collect())
+    }
+}
+
+impl Index<(usize, usize)> for DensityMatrix {
+    type Output = Complex<f64>;
+
+    fn index(&self, (r, c): (usize, usize)) -> &Complex<f64> {
+        &self.rho[(r << self.qubits) | c]
+    }
+}
+
+/// Density matrix of a state vector, or the matrix itself if it already is one.
+pub fn to_density(state: &DMatrix<Complex<f64>>) -> DMatrix<Complex<f64>> {
+    if state.ncols() == 1 {
+        state.clone() * adjoint(state)
+    } else {
+        state.clone()
+    }
+}
+
+/// Reduced density matrix of the `keep` qubits of `state`, which is either a
+/// state vector or a density matrix in the crate's `DMatrix` layout.
+///
+/// The first qubit in `keep` becomes the most significant one of the result.
+pub fn partial_trace(state: &DMatrix<Complex<f64>>, keep: &[usize]) -> DMatrix<Complex<f64>> {
+    let qubits = state.nrows().trailing_zeros() as usize;
+    let traced = (0..qubits).filter(|q| !keep.contains(q)).collect::<Vec<_>>();
+    let index = |kept: usize, rest: usize| {
+        let mut i = 0;
+        for (j, q) in keep.iter().enumerate() {
+            i |= ((kept >> (keep.len() - 1 - j)) & 1) << (qubits - 1 - q);
+        }
+        for (j, q) in traced.iter().enumerate() {
+            i |= ((rest >> (traced.len() - 1 - j)) & 1) << (qubits - 1 - q);
+        }
+        i
+    };
+    let dim = 1 << keep.len();
+    let mut result = DMatrix::new_zeros(dim, dim);
+    for r in 0..dim {
+        for c in 0..dim {
+            let mut sum = Complex::zero();
+            for e in 0..1 << traced.len() {
+                if state.ncols() == 1 {
+                    sum = sum + state[(index(r, e), 0)] * state[(index(c, e), 0)].conj();
+                } else {
+                    sum = sum + state[(index(r, e), index(c, e))];
+                }
+            }
+            result[(r, c)] = sum;
+        }
+    }
+    result
+}
+
+fn conjugate(m: &DMatrix<Complex<f64>>) -> DMatrix<Complex<f64>> {
+    let mut result = m.clone();
+    for e in result.as_mut_vector() {
+        *e = e.conj();
+    }
+    result
+}
+
+#[test]
+fn density_matrix_apply_test() {
+    use {hadamard, control_not, adjoint};
+    let mut s = StateVector::new(2);
+    s.apply(&hadamard(), &[0]);
+    s.apply(&control_not(0, 1, 2), &[0, 1]);
+    let mut rho = DensityMatrix::new(2);
+    rho.apply(&hadamard(), &[0]);
+    rho.apply(&control_not(0, 1, 2), &[0, 1]);
+    let expected = s.mat() * adjoint(&s.mat());
+    assert!(rho.mat().as_vector().iter().zip(expected.as_vector()).all(|(a, b)| (a - b).norm() < 0.000001));
+    assert!((rho.purity() - 1.).abs() < 0.000001);
+    assert_eq!(rho, DensityMatrix::from_mat(&s.mat()));
+}
+
+#[test]
+fn partial_trace_test() {
+    use {hadamard, control_not, not, kronecker_product, Qubit};
+    use metrics::{approx_eq, TOLERANCE};
+    let mut bell = StateVector::new(2);
+    bell.apply(&hadamard(), &[0]);
+    bell.apply(&control_not(0, 1, 2), &[0, 1]);
+    let mixed = DMatrix::from_column_vector(2, 2, &[Complex::new(0.5, 0.), Complex::zero(), Complex::zero(), Complex::new(0.5, 0.)]);
+    assert!(approx_eq(&mixed, &partial_trace(&bell.mat(), &[0]), TOLERANCE));
+    assert!(approx_eq(&mixed, &partial_trace(&bell.mat(), &[1]), TOLERANCE));
+    assert!(approx_eq(&mixed, &DensityMatrix::from_state_vector(&bell).partial_trace(&[1]).mat(), TOLERANCE));
+
+    let a = Qubit::new(Complex::new(0.6, 0.), Complex::new(0., 0.8));
+    let b = Qubit::one();
+    let c = Qubit::new(Complex::new(1. / 2f64.sqrt(), 0.), Complex::new(-1. / 2f64.sqrt(), 0.));
+    let abc = kronecker_product(&[a.mat().clone(), b.mat().clone(), c.mat().clone()]);
+    let pure = |q: &DMatrix<Complex<f64>>| DensityMatrix::from_mat(q).mat();
+    assert!(approx_eq(&pure(a.mat()), &partial_trace(&abc, &[0]), TOLERANCE));
+    assert!(approx_eq(&pure(c.mat()), &partial_trace(&pure(&abc), &[2]), TOLERANCE));
+    let ca = kronecker_product(&[c.mat().clone(), a.mat().clone()]);
+    assert!(approx_eq(&pure(&ca), &partial_trace(&abc, &[2, 0]), TOLERANCE));
+    assert!(approx_eq(&pure(&abc), &partial_trace(&abc, &[0, 1, 2]), TOLERANCE));
+    let mut flipped = StateVector::from_mat(&abc);
+    flipped.apply(&not(), &[1]);
+    assert!(approx_eq(&pure(&(not() * b.mat().clone())), &partial_trace(&flipped.mat(), &[1]), TOLERANCE));
+}
+
+#[test]
+fn density_matrix_measure_test() {
+    use {hadamard, control_not};
+    use noise::depolarizing;
+    use rand::{StdRng, SeedableRng};
+    let mut rng = StdRng::from_seed(&[5][..]);
+    for _ in 0..20 {
+        let mut rho = DensityMatrix::new(2);
+        rho.apply(&hadamard(), &[0]);
+        rho.apply(&control_not(0, 1, 2), &[0, 1]);
+        rho.apply_channel(&depolarizing(0.5), &[1]);
+        let a = rho.measure(0, &mut rng);
+        assert!((rho.trace().re - 1.).abs() < 0.000001);
+        assert!(rho.probabilities()[(1 - a) << 1].abs() < 0.000001);
+        assert!(rho.probabilities()[(1 - a) << 1 | 1].abs() < 0.000001);
+    }
+}
+
+#[test]
+fn density_matrix_channel_test() {
+    use {hadamard, not};
+    use noise::{depolarizing, amplitude_damping, phase_damping, bit_flip};
+    let mut rho = DensityMatrix::new(2);
+    rho.apply(&not(), &[1]);
+    rho.apply_channel(&amplitude_damping(1.), &[1]);
+    assert_eq!(DensityMatrix::new(2), rho);
+
+    let mut rho = DensityMatrix::new(1);
+    rho.apply(&hadamard(), &[0]);
+    rho.apply_channel(&phase_damping(1.), &[0]);
+    assert!((rho[(0, 1)].norm()) < 0.000001);
+    assert!((rho[(0, 0)].re - 0.5).abs() < 0.000001);
+
+    let mut rho = DensityMatrix::new(1);
+    rho.apply_channel(&depolarizing(1.), &[0]);
+    assert!((rho.purity() - 0.5).abs() < 0.000001);
+    assert!((rho.trace().re - 1.).abs() < 0.000001);
+
+    let mut rho = DensityMatrix::new(1);
+    rho.apply_channel(&bit_flip(0.25), &[0]);
+    assert!((rho[(1, 1)].re - 0.25).abs() < 0.000001);
+}
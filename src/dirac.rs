@@ -0,0 +1,384 @@
+use nalgebra::DMatrix;
+use num::{Complex, One, Zero};
+
+use std::f64::consts::FRAC_1_SQRT_2;
+
+use basis_n;
+
+/// How `format_state` prints amplitudes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DiracFormat {
+    /// Digits after the decimal point.
+    pub precision: usize,
+    /// Leave out terms whose amplitude rounds to zero.
+    pub hide_zeros: bool,
+    /// Print values like 1/√2 and √3/2 symbolically instead of as decimals.
+    pub constants: bool,
+}
+
+impl Default for DiracFormat {
+    fn default() -> DiracFormat {
+        DiracFormat {
+            precision: 3,
+            hide_zeros: true,
+            constants: false,
+        }
+    }
+}
+
+/// Magnitudes printed symbolically, as a numerator and a denominator.
+const CONSTANTS: [(f64, &'static str, &'static str); 6] = [
+    (FRAC_1_SQRT_2, "1", "√2"),
+    (0.5, "1", "2"),
+    (0.8660254037844386, "√3", "2"),
+    (0.5773502691896258, "1", "√3"),
+    (0.3535533905932738, "1", "(2√2)"),
+    (0.25, "1", "4"),
+];
+
+/// Writes a state vector as a sum of kets, e.g. `0.707|00⟩ + 0.707|11⟩`.
+pub fn format_state(state: &DMatrix<Complex<f64>>, format: &DiracFormat) -> String {
+    assert_eq!(1, state.ncols());
+    let qubits = state.nrows().trailing_zeros() as usize;
+    let zero = 0.5 * 10f64.powi(-(format.precision as i32));
+    let mut result = String::new();
+    for (i, bits) in basis_n(qubits).enumerate() {
+        let a = state[(i, 0)];
+        if format.hide_zeros && a.norm() < zero {
+            continue;
+        }
+        let coefficient = format_complex(a, format);
+        if result.is_empty() {
+            result.push_str(&coefficient);
+        } else if coefficient.starts_with('-') {
+            result.push_str(" - ");
+            result.push_str(&coefficient[1..]);
+        } else {
+            result.push_str(" + ");
+            result.push_str(&coefficient);
+        }
+        result.push('|');
+        for b in bits {
+            result.push_str(&b.to_string());
+        }
+        result.push('⟩');
+    }
+    if result.is_empty() {
+        result.push('0');
+    }
+    result
+}
+
+fn format_complex(a: Complex<f64>, format: &DiracFormat) -> String {
+    let zero = 0.5 * 10f64.powi(-(format.precision as i32));
+    if a.im.abs() < zero {
+        match format_real(a.re, "", format) {
+            ref s if s == "1" => String::new(),
+            ref s if s == "-1" => "-".to_string(),
+            s => s,
+        }
+    } else if a.re.abs() < zero {
+        format_real(a.im, "i", format)
+    } else {
+        let im = format_real(a.im, "i", format);
+        if im.starts_with('-') {
+            format!("({}-{})", format_real(a.re, "", format), &im[1..])
+        } else {
+            format!("({}+{})", format_real(a.re, "", format), im)
+        }
+    }
+}
+
+/// Formats `x` times `unit`, which is either empty or `i`. The unit goes in
+/// front of symbolic constants so that `i/√2` doesn't read as `1/(√2i)`.
+fn format_real(x: f64, unit: &str, format: &DiracFormat) -> String {
+    let sign = if x < 0. { "-" } else { "" };
+    if format.constants {
+        for &(value, numerator, denominator) in &CONSTANTS {
+            if (x.abs() - value).abs() < 1e-9 {
+                let numerator = match (unit, numerator) {
+                    ("", n) => n.to_string(),
+                    (u, "1") => u.to_string(),
+                    (u, n) => format!("{}{}", u, n),
+                };
+                return format!("{}{}/{}", sign, numerator, denominator);
+            }
+        }
+    }
+    let s = format!("{:.*}", format.precision, x.abs());
+    let s = if s.contains('.') {
+        s.trim_right_matches('0').trim_right_matches('.').to_string()
+    } else {
+        s
+    };
+    if s == "0" {
+        s
+    } else if s == "1" && !unit.is_empty() {
+        format!("{}{}", sign, unit)
+    } else {
+        format!("{}{}{}", sign, s, unit)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseError {
+    /// The character at this offset doesn't fit here.
+    Unexpected(usize),
+    /// The input ended in the middle of a term.
+    UnexpectedEnd,
+    /// The ket at this offset has a different number of qubits than the first one.
+    KetLength(usize),
+    /// There are no kets in the input.
+    Empty,
+}
+
+/// Builds a state vector from a sum of kets like `1/√2|00⟩ - i/√2|11⟩`.
+///
+/// Amplitudes can use decimals, `i`, `√` or `sqrt(...)`, parentheses and the
+/// operators `*` and `/`. Kets end with `⟩` or `>`, and repeated kets add up.
+/// The state isn't normalized.
+pub fn parse_state(input: &str) -> Result<DMatrix<Complex<f64>>, ParseError> {
+    let mut parser = Parser {
+        chars: input.chars().collect(),
+        pos: 0,
+    };
+    let mut terms = vec![];
+    parser.skip_whitespace();
+    let mut sign: Complex<f64> = match parser.peek() {
+        Some('-') => {
+            parser.pos += 1;
+            -Complex::one()
+        }
+        _ => Complex::one(),
+    };
+    loop {
+        parser.skip_whitespace();
+        let coefficient = if parser.peek() == Some('|') {
+            Complex::one()
+        } else {
+            parser.product()?
+        };
+        parser.skip_whitespace();
+        let start = parser.pos;
+        let ket = parser.ket()?;
+        terms.push((sign * coefficient, ket, start));
+        parser.skip_whitespace();
+        sign = match parser.next() {
+            Some('+') => Complex::one(),
+            Some('-') => -Complex::one(),
+            Some(_) => return Err(ParseError::Unexpected(parser.pos - 1)),
+            None => break,
+        };
+    }
+    let qubits = match terms.first() {
+        Some(t) => t.1.len(),
+        None => return Err(ParseError::Empty),
+    };
+    let mut result = DMatrix::new_zeros(1 << qubits, 1);
+    for (coefficient, ket, start) in terms {
+        if ket.len() != qubits {
+            return Err(ParseError::KetLength(start));
+        }
+        let i = ket.iter().fold(0, |i, b| (i << 1) | b);
+        result[(i, 0)] = result[(i, 0)] + coefficient;
+    }
+    Ok(result)
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).cloned()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek();
+        if c.is_some() {
+            self.pos += 1;
+        }
+        c
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().map_or(false, char::is_whitespace) {
+            self.pos += 1;
+        }
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), ParseError> {
+        self.skip_whitespace();
+        match self.next() {
+            Some(n) if n == c => Ok(()),
+            Some(_) => Err(ParseError::Unexpected(self.pos - 1)),
+            None => Err(ParseError::UnexpectedEnd),
+        }
+    }
+
+    fn ket(&mut self) -> Result<Vec<usize>, ParseError> {
+        self.expect('|')?;
+        let mut bits = vec![];
+        loop {
+            match self.next() {
+                Some('0') => bits.push(0),
+                Some('1') => bits.push(1),
+                Some('⟩') | Some('>') if !bits.is_empty() => return Ok(bits),
+                Some(_) => return Err(ParseError::Unexpected(self.pos - 1)),
+                None => return Err(ParseError::UnexpectedEnd),
+            }
+        }
+    }
+
+    fn sum(&mut self) -> Result<Complex<f64>, ParseError> {
+        self.skip_whitespace();
+        let mut result = match self.peek() {
+            Some('-') | Some('+') => Complex::zero(),
+            _ => self.product()?,
+        };
+        loop {
+            self.skip_whitespace();
+            match self.peek() {
+                Some('+') => {
+                    self.pos += 1;
+                    result = result + self.product()?;
+                }
+                Some('-') => {
+                    self.pos += 1;
+                    result = result - self.product()?;
+                }
+                _ => return Ok(result),
+            }
+        }
+    }
+
+    fn product(&mut self) -> Result<Complex<f64>, ParseError> {
+        let mut result = self.unary()?;
+        loop {
+            self.skip_whitespace();
+            match self.peek() {
+                Some('*') | Some('·') => {
+                    self.pos += 1;
+                    result = result * self.unary()?;
+                }
+                Some('/') => {
+                    self.pos += 1;
+                    result = result / self.unary()?;
+                }
+                Some(c) if c.is_digit(10) || c == '.' || c == '√' || c == '(' || c == 'i' || c == 's' => {
+                    result = result * self.unary()?;
+                }
+                _ => return Ok(result),
+            }
+        }
+    }
+
+    fn unary(&mut self) -> Result<Complex<f64>, ParseError> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('-') => {
+                self.pos += 1;
+                Ok(-self.unary()?)
+            }
+            Some('+') => {
+                self.pos += 1;
+                self.unary()
+            }
+            _ => self.atom(),
+        }
+    }
+
+    fn atom(&mut self) -> Result<Complex<f64>, ParseError> {
+        self.skip_whitespace();
+        let start = self.pos;
+        match self.next() {
+            Some('i') => Ok(Complex::new(0., 1.)),
+            Some('√') => Ok(self.atom()?.sqrt()),
+            Some('(') => {
+                let result = self.sum()?;
+                self.expect(')')?;
+                Ok(result)
+            }
+            Some('s') => {
+                for c in "qrt".chars() {
+                    if self.next() != Some(c) {
+                        return Err(ParseError::Unexpected(start));
+                    }
+                }
+                self.expect('(')?;
+                let result = self.sum()?;
+                self.expect(')')?;
+                Ok(result.sqrt())
+            }
+            Some(c) if c.is_digit(10) || c == '.' => {
+                while self.peek().map_or(false, |c| c.is_digit(10) || c == '.') {
+                    self.pos += 1;
+                }
+                let text = self.chars[start..self.pos].iter().cloned().collect::<String>();
+                let value = match text.parse::<f64>() {
+                    Ok(v) => v,
+                    Err(_) => return Err(ParseError::Unexpected(start)),
+                };
+                if self.peek() == Some('i') {
+                    self.pos += 1;
+                    Ok(Complex::new(0., value))
+                } else {
+                    Ok(Complex::new(value, 0.))
+                }
+            }
+            Some(_) => Err(ParseError::Unexpected(start)),
+            None => Err(ParseError::UnexpectedEnd),
+        }
+    }
+}
+
+#[cfg(test)]
+fn state(amplitudes: &[(f64, f64)]) -> DMatrix<Complex<f64>> {
+    let amplitudes = amplitudes.iter().map(|&(re, im)| Complex::new(re, im)).collect::<Vec<_>>();
+    DMatrix::from_column_vector(amplitudes.len(), 1, &amplitudes)
+}
+
+#[test]
+fn format_state_test() {
+    let y = 1. / 2f64.sqrt();
+    let bell = state(&[(y, 0.), (0., 0.), (0., 0.), (y, 0.)]);
+    assert_eq!("0.707|00⟩ + 0.707|11⟩", format_state(&bell, &DiracFormat::default()));
+    let constants = DiracFormat { constants: true, ..DiracFormat::default() };
+    assert_eq!("1/√2|00⟩ + 1/√2|11⟩", format_state(&bell, &constants));
+    let all = DiracFormat { hide_zeros: false, precision: 2, ..DiracFormat::default() };
+    assert_eq!("0.71|00⟩ + 0|01⟩ + 0|10⟩ + 0.71|11⟩", format_state(&bell, &all));
+
+    let s = state(&[(0.5, 0.), (0., -0.5), (-0.5, 0.), (0.5, 0.5)]);
+    assert_eq!("0.5|00⟩ - 0.5i|01⟩ - 0.5|10⟩ + (0.5+0.5i)|11⟩", format_state(&s, &DiracFormat::default()));
+    assert_eq!("1/2|00⟩ - i/2|01⟩ - 1/2|10⟩ + (1/2+i/2)|11⟩", format_state(&s, &constants));
+    assert_eq!("-i|1⟩", format_state(&state(&[(0., 0.), (0., -1.)]), &DiracFormat::default()));
+    assert_eq!("|0⟩", format_state(&state(&[(1., 0.), (0., 0.)]), &DiracFormat::default()));
+    assert_eq!("0", format_state(&state(&[(0., 0.), (0.0001, 0.)]), &DiracFormat::default()));
+}
+
+#[test]
+fn parse_state_test() {
+    use metrics::{approx_eq, TOLERANCE};
+    let y = 1. / 2f64.sqrt();
+    let bell = state(&[(y, 0.), (0., 0.), (0., 0.), (y, 0.)]);
+    assert!(approx_eq(&bell, &parse_state("1/√2|00⟩ + 1/√2|11⟩").unwrap(), TOLERANCE));
+    assert!(approx_eq(&bell, &parse_state("0.70710678|00> + sqrt(0.5)|11>").unwrap(), TOLERANCE));
+    assert!(approx_eq(&state(&[(0., 0.), (0., -1.)]), &parse_state("-i|1⟩").unwrap(), TOLERANCE));
+    assert!(approx_eq(&state(&[(0.5, 0.), (0.5, -0.5)]), &parse_state("1/2 |0⟩ + (1/2 - i/2)|1⟩").unwrap(), TOLERANCE));
+    assert!(approx_eq(&state(&[(0., 0.), (1., 0.)]), &parse_state("0.25|1⟩ + 3 * 0.25|1⟩").unwrap(), TOLERANCE));
+    assert!(approx_eq(&state(&[(-1., 0.), (0., 0.)]), &parse_state("-|0⟩").unwrap(), TOLERANCE));
+
+    assert_eq!(Err(ParseError::KetLength(15)), parse_state("1/√2|00⟩ + 1/√2|1⟩"));
+    assert_eq!(Err(ParseError::Unexpected(4)), parse_state("0.5|2⟩"));
+    assert_eq!(Err(ParseError::UnexpectedEnd), parse_state("0.5|01"));
+    assert_eq!(Err(ParseError::Unexpected(1)), parse_state("1x|0⟩"));
+
+    for s in [bell.clone(), state(&[(0.5, 0.), (0., -0.5), (-0.5, 0.), (0.5, 0.5)])].iter() {
+        let constants = DiracFormat { constants: true, precision: 9, ..DiracFormat::default() };
+        assert!(approx_eq(s, &parse_state(&format_state(s, &constants)).unwrap(), TOLERANCE));
+        let decimals = DiracFormat { precision: 9, ..DiracFormat::default() };
+        assert!(approx_eq(s, &parse_state(&format_state(s, &decimals)).unwrap(), TOLERANCE));
+    }
+}
@@ -0,0 +1,117 @@
+use nalgebra::DMatrix;
+use num::Complex;
+
+use density::{partial_trace, to_density};
+use linalg::{hermitian_eigenvalues, hermitian_map};
+use {kronecker_product, pauli_y};
+
+/// Von Neumann entropy -Tr(ρ log₂ ρ) in bits of a state vector or density matrix.
+pub fn von_neumann_entropy(state: &DMatrix<Complex<f64>>) -> f64 {
+    if state.ncols() == 1 {
+        return 0.;
+    }
+    hermitian_eigenvalues(state).into_iter()
+        .filter(|&x| x > 1e-12)
+        .map(|x| -x * x.log2())
+        .sum()
+}
+
+/// Entropy of the reduced state of the `subsystem` qubits.
+///
+/// For a pure state this is the entanglement between `subsystem` and the rest.
+pub fn entanglement_entropy(state: &DMatrix<Complex<f64>>, subsystem: &[usize]) -> f64 {
+    von_neumann_entropy(&partial_trace(state, subsystem))
+}
+
+/// Wootters concurrence of a two qubit state vector or density matrix, from 0
+/// for separable states to 1 for Bell states.
+pub fn concurrence(state: &DMatrix<Complex<f64>>) -> f64 {
+    assert_eq!(4, state.nrows());
+    let rho = to_density(state);
+    let yy = kronecker_product(&[pauli_y(), pauli_y()]);
+    let mut conjugated = rho.clone();
+    for e in conjugated.as_mut_vector() {
+        *e = e.conj();
+    }
+    let flipped = yy.clone() * conjugated * yy;
+    let root = hermitian_map(&rho, |x| x.max(0.).sqrt());
+    let mut l = hermitian_eigenvalues(&(root.clone() * flipped * root)).into_iter()
+        .map(|x| x.max(0.).sqrt())
+        .collect::<Vec<_>>();
+    l.reverse();
+    (l[0] - l[1] - l[2] - l[3]).max(0.)
+}
+
+/// Transposes the `qubits` subsystem of a density matrix.
+pub fn partial_transpose(rho: &DMatrix<Complex<f64>>, qubits: &[usize]) -> DMatrix<Complex<f64>> {
+    let n = rho.nrows().trailing_zeros() as usize;
+    let mask = qubits.iter().fold(0, |m, q| m | 1 << (n - 1 - q));
+    let mut result = DMatrix::new_zeros(rho.nrows(), rho.ncols());
+    for r in 0..rho.nrows() {
+        for c in 0..rho.ncols() {
+            let swapped = (r ^ c) & mask;
+            result[(r ^ swapped, c ^ swapped)] = rho[(r, c)];
+        }
+    }
+    result
+}
+
+/// Negativity of the bipartition between `subsystem` and the rest, the sum of
+/// the magnitudes of the negative eigenvalues of the partial transpose.
+pub fn negativity(state: &DMatrix<Complex<f64>>, subsystem: &[usize]) -> f64 {
+    let transposed = partial_transpose(&to_density(state), subsystem);
+    hermitian_eigenvalues(&transposed).into_iter()
+        .filter(|&x| x < 0.)
+        .map(|x| -x)
+        .sum()
+}
+
+/// log₂ of the trace norm of the partial transpose, equal to log₂(2N + 1) for negativity N.
+pub fn log_negativity(state: &DMatrix<Complex<f64>>, subsystem: &[usize]) -> f64 {
+    (2. * negativity(state, subsystem) + 1.).log2()
+}
+
+#[cfg(test)]
+fn bell() -> DMatrix<Complex<f64>> {
+    use num::{One, Zero};
+    use num::Complex as C;
+    DMatrix::from_column_vector(4, 1, &[C::one(), C::zero(), C::zero(), C::one()]) * C::new(1. / 2f64.sqrt(), 0.)
+}
+
+#[test]
+fn bell_state_test() {
+    let b = bell();
+    assert!((entanglement_entropy(&b, &[0]) - 1.).abs() < 0.000001);
+    assert!((entanglement_entropy(&b, &[1]) - 1.).abs() < 0.000001);
+    assert!(von_neumann_entropy(&b).abs() < 0.000001);
+    assert!(von_neumann_entropy(&to_density(&b)).abs() < 0.000001);
+    assert!((concurrence(&b) - 1.).abs() < 0.000001);
+    assert!((negativity(&b, &[0]) - 0.5).abs() < 0.000001);
+    assert!((log_negativity(&b, &[1]) - 1.).abs() < 0.000001);
+}
+
+#[test]
+fn separable_state_test() {
+    use {Qubit, kronecker_product};
+    let a = Qubit::new(Complex::new(0.6, 0.), Complex::new(0., 0.8));
+    let b = Qubit::new(Complex::new(1. / 2f64.sqrt(), 0.), Complex::new(-1. / 2f64.sqrt(), 0.));
+    let ab = kronecker_product(&[a.mat().clone(), b.mat().clone()]);
+    assert!(entanglement_entropy(&ab, &[0]).abs() < 0.000001);
+    assert!(concurrence(&ab).abs() < 0.000001);
+    assert!(negativity(&ab, &[1]).abs() < 0.000001);
+}
+
+#[test]
+fn werner_state_test() {
+    // p |Bell><Bell| + (1 - p) I/4 is entangled exactly when p > 1/3.
+    for &p in &[0.2, 1. / 3., 0.5, 0.8] {
+        let mut rho = to_density(&bell()) * Complex::new(p, 0.);
+        for i in 0..4 {
+            rho[(i, i)] = rho[(i, i)] + Complex::new((1. - p) / 4., 0.);
+        }
+        let expected = ((3. * p - 1.) / 2f64).max(0.);
+        assert!((concurrence(&rho) - expected).abs() < 0.000001);
+        let expected = ((3. * p - 1.) / 4f64).max(0.);
+        assert!((negativity(&rho, &[0]) - expected).abs() < 0.000001);
+    }
+}
@@ -22,6 +22,7 @@ use nalgebra::{DMatrix, Vector4, Eye};
 use num::{Complex, One, Zero};
 
 use quantum_circuit_wars::*;
+use quantum_circuit_wars::circuit::gate::{self, Gate};
 
 pub mod renderer;
 pub mod fonts;
@@ -62,6 +63,9 @@ impl Model {
 
 implement_vertex!(Vertex, position, tex_coords);
 
+/// Backend the players' qubits are simulated on.
+pub type Simulator = StateVector;
+
 pub struct RenderContext<'a> {
     pub fonts: Fonts<'a>,
     pub cam: Mat,
@@ -75,7 +79,7 @@ pub struct RenderContext<'a> {
     pub level: Vec<(usize, ABox)>,
     pub big_block_target: usize,
     pub line_end: f32,
-    pub state: DMatrix<Complex<f64>>,
+    pub state: Simulator,
     pub goal_a: Vector4<Complex<f64>>,
     pub goal_b: Vector4<Complex<f64>>,
     pub score_a: f64,
@@ -103,17 +107,26 @@ impl Type {
         }
     }
 
-    pub fn mat(&self) -> DMatrix<Complex<f64>> {
+    pub fn gate(&self) -> Option<Box<Gate>> {
+        use self::Type::*;
+        match *self {
+            Not => Some(gate::Not::new()),
+            Y => Some(gate::PauliY::new()),
+            Z => Some(gate::PauliZ::new()),
+            H => Some(gate::Hadamard::new()),
+            CNotA | CNotB => Some(gate::ControlNot::new()),
+            Ident2 | Ident4 => None,
+        }
+    }
+
+    /// Qubits the gate acts on in port order. Small boxes act on Alice's qubit
+    /// when they are in the upper half and on Bob's otherwise.
+    pub fn qubits(&self, upper: bool) -> Vec<usize> {
         use self::Type::*;
         match *self {
-            Not => not(),
-            Y => pauli_y(),
-            Z => pauli_z(),
-            H => hadamard(),
-            CNotA => control_not(0, 1, 2),
-            CNotB => control_not(1, 0, 2),
-            Ident2 => DMatrix::new_identity(2),
-            Ident4 => DMatrix::new_identity(4),
+            CNotA | Ident4 => vec![0, 1],
+            CNotB => vec![1, 0],
+            _ => if upper { vec![0] } else { vec![1] },
         }
     }
 
@@ -267,7 +280,12 @@ impl<'a> RenderContext<'a> {
                 (1800, ABox{pos: Vect::new(1.2, -0.5), typ: Type::CNotB})],
             big_block_target: 600,
             line_end: 1.,
-            state: DMatrix::from_column_vector(4, 1, &[C::one(), C::zero(), C::zero(), C::one()]) * C::new(1. / 2f64.sqrt(), 0.),
+            state: {
+                let mut state = Simulator::new(2);
+                state.apply_gate(&*gate::Hadamard::new(), &[0]);
+                state.apply_gate(&*gate::ControlNot::new(), &[0, 1]);
+                state
+            },
             goal_a: Vector4::new(C::zero(), C::one(), C::one(), C::zero()) * C::new(1. / 2f64.sqrt(), 0.),
             goal_b: Vector4::new(C::zero(), C::one(), -C::one(), C::zero()) * C::new(1. / 2f64.sqrt(), 0.),
             score_a: 0.,
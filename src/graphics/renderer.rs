@@ -4,7 +4,7 @@ use glium::index::{IndexBuffer, PrimitiveType};
 use glium::draw_parameters::DrawParameters;
 use glium::uniforms::{Uniforms, UniformsStorage, AsUniformValue, MagnifySamplerFilter, MinifySamplerFilter};
 
-use nalgebra::{Vector4, Dot, Norm, Iterable};
+use nalgebra::{DMatrix, Vector4, Dot, Norm, Iterable};
 use num::{Complex, Zero};
 use std::convert::AsRef;
 use baal;
@@ -13,6 +13,7 @@ use {GameContext, Node, Vect};
 use super::{RenderContext, Vertex, vert};
 use math::*;
 use quantum_circuit_wars::*;
+use quantum_circuit_wars::metrics::fidelity;
 use quantum_circuit_wars::circuit::{GameView, Game, Port};
 
 fn input_pos(gen: &Game<Node>, input: Port<u32>, _size: f32) -> Vect {
@@ -191,9 +192,13 @@ pub fn render(display: &Display, rctx: &mut RenderContext, world: GameView<Node>
             target.draw(&model.vertices, &model.indices, &program, &uniforms, &draw_params).expect("Drawing node failed.");
         }
     }
-    let state = Vector4::new(rctx.state[(0, 0)], rctx.state[(1, 0)], rctx.state[(2, 0)], rctx.state[(3, 0)]);
-    rctx.score_a = calc_score(state.clone(), rctx.goal_a.clone());
-    rctx.score_b = calc_score(state, rctx.goal_b.clone());
+    // Simulators with noise can leave the state mixed, which has no amplitudes.
+    let state = match Backend::amplitudes(&rctx.state) {
+        Some(amplitudes) => DMatrix::from_column_vector(amplitudes.len(), 1, &amplitudes),
+        None => Backend::density(&rctx.state),
+    };
+    rctx.score_a = calc_score(&state, &rctx.goal_a);
+    rctx.score_b = calc_score(&state, &rctx.goal_b);
 
     let string = format!("Alice: {}", round(rctx.score_a, 4));
     rctx.fonts.draw_text(display, &mut target, "press_start_2p", 20., [1., 0., 0., 1.], Vect::new(0.45, -1.8), &string);
@@ -232,14 +237,9 @@ pub fn render(display: &Display, rctx: &mut RenderContext, world: GameView<Node>
 
     target.finish().unwrap();
     for i in to_be_removed.into_iter().rev() {
-        if rctx.boxes[i].typ.is_big() {
-            rctx.state = rctx.boxes[i].typ.mat() * rctx.state.clone();
-        } else {
-            if rctx.boxes[i].pos.y > 0. {
-                rctx.state = apply_to_qubit(rctx.boxes[i].typ.mat(), 0, 2) * rctx.state.clone();
-            } else {
-                rctx.state = apply_to_qubit(rctx.boxes[i].typ.mat(), 1, 2) * rctx.state.clone();
-            }
+        if let Some(gate) = rctx.boxes[i].typ.gate() {
+            let qubits = rctx.boxes[i].typ.qubits(rctx.boxes[i].pos.y > 0.);
+            rctx.state.apply_gate(&*gate, &qubits);
         }
         rctx.boxes.remove(i);
     }
@@ -274,11 +274,10 @@ fn round_test() {
     assert_eq!(0.12346, round(0.123456789, 5));
 }
 
-fn calc_score(state: Vector4<Complex<f64>>, goal: Vector4<Complex<f64>>) -> f64 {
-    state.iter()
-        .zip(goal.iter())
-        .map(|(a, b)| a * b)
-        .fold(Complex::zero(), |a, b| a + b).norm_sqr()
+/// `state` is either a state vector or a density matrix.
+fn calc_score(state: &DMatrix<Complex<f64>>, goal: &Vector4<Complex<f64>>) -> f64 {
+    let goal = DMatrix::from_column_vector(4, 1, &[goal[0], goal[1], goal[2], goal[3]]);
+    fidelity(&goal, state)
 }
 
 pub fn render_splashscreen(display: &Display, render_context: &mut RenderContext, ctx: &mut GameContext) {
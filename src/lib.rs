@@ -3,15 +3,37 @@ extern crate nalgebra;
 extern crate num;
 extern crate itertools;
 extern crate daggy;
+extern crate rand;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_json;
+extern crate bincode;
 
 use num::{Complex, One, Zero};
 use nalgebra::{DMatrix, Eye};
 
 use std::ops::{Index, Mul};
+use std::f64;
 
 use circuit::*;
 
+pub use state::StateVector;
+pub use density::DensityMatrix;
+pub use backend::Backend;
+
 pub mod circuit;
+pub mod state;
+pub mod density;
+pub mod noise;
+pub mod measure;
+pub mod linalg;
+pub mod metrics;
+pub mod entanglement;
+pub mod bloch;
+pub mod dirac;
+pub mod stabilizer;
+pub mod backend;
 
 #[derive(Clone, Debug)]
 pub struct Qubit {
@@ -40,7 +62,7 @@ impl Index<usize> for Qubit {
     type Output = Complex<f64>;
 
     fn index(&self, n: usize) -> &Complex<f64> {
-        &self.q[(0, n)]
+        &self.q[(n, 0)]
     }
 }
 
@@ -62,6 +84,16 @@ pub fn kronecker_product<T: Mul + Zero + One + Clone + Copy>(matrices: &[DMatrix
     })
 }
 
+pub fn adjoint(m: &DMatrix<Complex<f64>>) -> DMatrix<Complex<f64>> {
+    let mut result = DMatrix::new_zeros(m.ncols(), m.nrows());
+    for r in 0..m.nrows() {
+        for c in 0..m.ncols() {
+            result[(c, r)] = m[(r, c)].conj();
+        }
+    }
+    result
+}
+
 pub fn apply_to_qubit(gate: DMatrix<Complex<f64>>, index: usize, amount: usize) -> DMatrix<Complex<f64>> {
     let mut vec: Vec<_> = ::std::iter::repeat(DMatrix::new_identity(2))
         .take(amount - 1)
@@ -77,6 +109,14 @@ pub fn not() -> DMatrix<Complex<f64>> {
           C::one(), C::zero()])
 }
 
+/// Square root of `not`, also known as SX.
+pub fn sqrt_not() -> DMatrix<Complex<f64>> {
+    use self::num::Complex as C;
+    DMatrix::from_column_vector(2, 2,
+        &[C::new(0.5, 0.5), C::new(0.5, -0.5),
+          C::new(0.5, -0.5), C::new(0.5, 0.5)])
+}
+
 pub fn pauli_y() -> DMatrix<Complex<f64>> {
     use self::num::Complex as C;
     DMatrix::from_column_vector(2, 2,
@@ -100,6 +140,65 @@ pub fn hadamard() -> DMatrix<Complex<f64>> {
         ])
 }
 
+pub fn rx(theta: f64) -> DMatrix<Complex<f64>> {
+    use self::num::Complex as C;
+    let c = C::new((theta / 2.).cos(), 0.);
+    let s = C::new(0., -(theta / 2.).sin());
+    DMatrix::from_column_vector(2, 2,
+        &[c, s,
+          s, c])
+}
+
+pub fn ry(theta: f64) -> DMatrix<Complex<f64>> {
+    use self::num::Complex as C;
+    let c = C::new((theta / 2.).cos(), 0.);
+    let s = C::new((theta / 2.).sin(), 0.);
+    DMatrix::from_column_vector(2, 2,
+        &[c,  s,
+          -s, c])
+}
+
+pub fn rz(theta: f64) -> DMatrix<Complex<f64>> {
+    use self::num::Complex as C;
+    DMatrix::from_column_vector(2, 2,
+        &[C::from_polar(&1., &(-theta / 2.)), C::zero(),
+          C::zero(), C::from_polar(&1., &(theta / 2.))])
+}
+
+pub fn phase(phi: f64) -> DMatrix<Complex<f64>> {
+    use self::num::Complex as C;
+    DMatrix::from_column_vector(2, 2,
+        &[C::one(), C::zero(),
+          C::zero(), C::from_polar(&1., &phi)])
+}
+
+pub fn s() -> DMatrix<Complex<f64>> {
+    phase(f64::consts::PI / 2.)
+}
+
+pub fn s_dagger() -> DMatrix<Complex<f64>> {
+    phase(-f64::consts::PI / 2.)
+}
+
+pub fn t() -> DMatrix<Complex<f64>> {
+    phase(f64::consts::PI / 4.)
+}
+
+pub fn t_dagger() -> DMatrix<Complex<f64>> {
+    phase(-f64::consts::PI / 4.)
+}
+
+/// General single-qubit gate. `u3(theta, phi, lambda)` takes |0> to the state
+/// `gate::Input` prepares with the same `theta` and `phi`.
+pub fn u3(theta: f64, phi: f64, lambda: f64) -> DMatrix<Complex<f64>> {
+    use self::num::Complex as C;
+    let c = (theta / 2.).cos();
+    let s = (theta / 2.).sin();
+    DMatrix::from_column_vector(2, 2,
+        &[C::new(c, 0.), C::from_polar(&s, &phi),
+          -C::from_polar(&s, &lambda), C::from_polar(&c, &(phi + lambda))])
+}
+
 pub fn control_not(control: usize, target: usize, qubits: usize) -> DMatrix<Complex<f64>> {
     let mut result = DMatrix::new_zeros(2usize.pow(qubits as u32), 2usize.pow(qubits as u32));
     let basis_out = basis_n(qubits)
@@ -119,6 +218,117 @@ pub fn control_not(control: usize, target: usize, qubits: usize) -> DMatrix<Comp
     result
 }
 
+pub fn swap(a: usize, b: usize, qubits: usize) -> DMatrix<Complex<f64>> {
+    let mut result = DMatrix::new_zeros(2usize.pow(qubits as u32), 2usize.pow(qubits as u32));
+    let basis_out = basis_n(qubits)
+        .map(|mut v| {
+            v.swap(a, b);
+            v
+        });
+    for (i, b) in basis_out.enumerate() {
+        let mut n = 0;
+        for (j, c) in b.iter().enumerate() {
+            n += c * 2usize.pow((qubits - 1 - j) as u32);
+        }
+        result[(n, i)] = Complex::one();
+    }
+    result
+}
+
+/// Swaps `a` and `b`, multiplying the amplitude by i whenever they differ.
+pub fn iswap(a: usize, b: usize, qubits: usize) -> DMatrix<Complex<f64>> {
+    let mut result = swap(a, b, qubits);
+    for (i, v) in basis_n(qubits).enumerate() {
+        if v[a] != v[b] {
+            for r in 0..result.nrows() {
+                result[(r, i)] = result[(r, i)] * Complex::new(0., 1.);
+            }
+        }
+    }
+    result
+}
+
+pub fn toffoli(first: usize, second: usize, target: usize, qubits: usize) -> DMatrix<Complex<f64>> {
+    controlled(&[first, second], &[], &not(), &[target], qubits)
+}
+
+pub fn fredkin(control: usize, a: usize, b: usize, qubits: usize) -> DMatrix<Complex<f64>> {
+    controlled(&[control], &[], &swap(0, 1, 2), &[a, b], qubits)
+}
+
+/// Applies `gate` to the `targets` qubits of a `qubits` qubit register when all
+/// `controls` are 1 and all `negative` controls are 0, and leaves it alone otherwise.
+///
+/// The first target is the most significant qubit of `gate`, as in `apply_to_qubit`.
+pub fn controlled(controls: &[usize], negative: &[usize], gate: &DMatrix<Complex<f64>>, targets: &[usize], qubits: usize) -> DMatrix<Complex<f64>> {
+    assert_eq!(2usize.pow(targets.len() as u32), gate.nrows());
+    assert_eq!(gate.nrows(), gate.ncols());
+    let bit = |q: &usize| 1 << (qubits - 1 - q);
+    let target_mask = targets.iter().map(&bit).fold(0, |a, b| a | b);
+    let dim = 2usize.pow(qubits as u32);
+    let mut result = DMatrix::new_zeros(dim, dim);
+    for i in 0..dim {
+        let active = controls.iter().all(|c| i & bit(c) != 0)
+            && negative.iter().all(|c| i & bit(c) == 0);
+        if !active {
+            result[(i, i)] = Complex::one();
+            continue;
+        }
+        let local = targets.iter().fold(0, |a, t| (a << 1) | ((i & bit(t) != 0) as usize));
+        for l in 0..gate.nrows() {
+            let mut j = i & !target_mask;
+            for (k, t) in targets.iter().enumerate() {
+                if (l >> (targets.len() - 1 - k)) & 1 == 1 {
+                    j |= bit(t);
+                }
+            }
+            result[(j, i)] = gate[(l, local)];
+        }
+    }
+    result
+}
+
+#[test]
+fn qubit_index_test() {
+    let qubit = Qubit::new(Complex::new(0.6, 0.), Complex::new(0., 0.8));
+    assert_eq!(Complex::new(0.6, 0.), qubit[0]);
+    assert_eq!(Complex::new(0., 0.8), qubit[1]);
+}
+
+#[test]
+fn controlled_test() {
+    use self::nalgebra::DMatrix as M;
+    assert_eq!(control_not(0, 1, 3), controlled(&[0], &[], &not(), &[1], 3));
+    assert_eq!(control_not(2, 0, 3), controlled(&[2], &[], &not(), &[0], 3));
+
+    let mut toffoli = M::new_identity(8);
+    toffoli[(6, 6)] = Complex::zero();
+    toffoli[(7, 7)] = Complex::zero();
+    toffoli[(6, 7)] = Complex::one();
+    toffoli[(7, 6)] = Complex::one();
+    assert_eq!(toffoli, controlled(&[0, 1], &[], &not(), &[2], 3));
+
+    let mut cz = M::new_identity(4);
+    cz[(3, 3)] = -Complex::one();
+    assert_eq!(cz, controlled(&[0], &[], &pauli_z(), &[1], 2));
+    assert_eq!(cz, controlled(&[1], &[], &pauli_z(), &[0], 2));
+
+    let ch = controlled(&[0], &[], &hadamard(), &[1], 2);
+    let id: DMatrix<Complex<f64>> = M::new_identity(4);
+    assert!((ch.clone() * ch.clone()).as_vector().iter().zip(id.as_vector()).all(|(a, b)| (a - b).norm() < 0.000001));
+    assert_eq!(hadamard()[(1, 1)], ch[(3, 3)]);
+    assert_eq!(Complex::one(), ch[(1, 1)]);
+
+    let negated = controlled(&[], &[0], &not(), &[1], 2);
+    assert_eq!(apply_to_qubit(not(), 0, 2) * control_not(0, 1, 2) * apply_to_qubit(not(), 0, 2), negated);
+
+    let swap = control_not(0, 1, 2) * control_not(1, 0, 2) * control_not(0, 1, 2);
+    assert_eq!(kronecker_product(&[M::new_identity(2), swap.clone()]), controlled(&[], &[], &swap, &[1, 2], 3));
+    let cswap = controlled(&[0], &[], &swap, &[2, 1], 3);
+    assert_eq!(Complex::one(), cswap[(6, 5)]);
+    assert_eq!(Complex::one(), cswap[(1, 1)]);
+}
+
 #[test]
 fn control_not_test() {
     let r = DMatrix::from_column_vector(8, 8, &[
@@ -134,6 +344,43 @@ fn control_not_test() {
     assert_eq!(r, control_not(0, 1, 3));
 }
 
+#[test]
+fn swap_test() {
+    use self::nalgebra::DMatrix as M;
+    let swapped = control_not(0, 1, 2) * control_not(1, 0, 2) * control_not(0, 1, 2);
+    assert_eq!(swapped, swap(0, 1, 2));
+    assert_eq!(swap(1, 0, 2), swap(0, 1, 2));
+    assert_eq!(kronecker_product(&[swapped, M::new_identity(2)]), swap(0, 1, 3));
+    let i = Complex::new(0., 1.);
+    let is = iswap(0, 1, 2);
+    assert_eq!(Complex::one(), is[(0, 0)]);
+    assert_eq!(i, is[(2, 1)]);
+    assert_eq!(i, is[(1, 2)]);
+    assert_eq!(Complex::one(), is[(3, 3)]);
+    assert_eq!(iswap(0, 2, 3), iswap(2, 0, 3));
+}
+
+#[test]
+fn toffoli_fredkin_test() {
+    for (i, v) in basis_n(3).enumerate() {
+        let t = toffoli(0, 2, 1, 3);
+        let mut w = v.clone();
+        if v[0] == 1 && v[2] == 1 {
+            w[1] ^= 1;
+        }
+        let j = w[0] * 4 + w[1] * 2 + w[2];
+        assert_eq!(Complex::one(), t[(j, i)]);
+
+        let f = fredkin(1, 0, 2, 3);
+        let mut w = v.clone();
+        if v[1] == 1 {
+            w.swap(0, 2);
+        }
+        let j = w[0] * 4 + w[1] * 2 + w[2];
+        assert_eq!(Complex::one(), f[(j, i)]);
+    }
+}
+
 pub struct BasisIter {
     n: usize,
     i: usize,
@@ -282,6 +529,29 @@ fn apply_to_qubit_test() {
     assert_eq!(r, apply_to_qubit(not(), 2, 3));
 }
 
+#[test]
+fn rotation_test() {
+    use std::f64::consts::PI;
+    use metrics::{approx_eq, TOLERANCE};
+    let i = Complex::new(0., 1.);
+    assert!(approx_eq(&(not() * -i), &rx(PI), TOLERANCE));
+    assert!(approx_eq(&(pauli_y() * -i), &ry(PI), TOLERANCE));
+    assert!(approx_eq(&(pauli_z() * -i), &rz(PI), TOLERANCE));
+    assert!(approx_eq(&pauli_z(), &phase(PI), TOLERANCE));
+    assert!(approx_eq(&pauli_z(), &(s() * s()), TOLERANCE));
+    assert!(approx_eq(&s(), &(t() * t()), TOLERANCE));
+    assert!(approx_eq(&DMatrix::new_identity(2), &(s() * s_dagger()), TOLERANCE));
+    assert!(approx_eq(&DMatrix::new_identity(2), &(t() * t_dagger()), TOLERANCE));
+    assert!(approx_eq(&hadamard(), &u3(PI / 2., 0., PI), TOLERANCE));
+    assert!(approx_eq(&phase(0.3), &u3(0., 0., 0.3), TOLERANCE));
+    assert!(approx_eq(&(rz(0.7) * Complex::from_polar(&1., &0.35)), &phase(0.7), TOLERANCE));
+    assert!(approx_eq(&(ry(0.4) * rx(0.2) * rx(-0.2) * ry(-0.4)), &DMatrix::new_identity(2), TOLERANCE));
+
+    use circuit::gate::{Gate, Input};
+    let input = Input::with_angles(1.1, 0.4).process(&[]).remove(0);
+    assert!(approx_eq(&input, &(u3(1.1, 0.4, 2.) * Qubit::one().mat().clone()), TOLERANCE));
+}
+
 #[test]
 fn kronecker_product_matrix_test() {
     use self::nalgebra::DMatrix as M;
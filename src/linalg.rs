@@ -0,0 +1,165 @@
+use nalgebra::DMatrix;
+use num::{Complex, Zero};
+use itertools::Itertools;
+
+use adjoint;
+
+pub fn trace(m: &DMatrix<Complex<f64>>) -> Complex<f64> {
+    (0..m.nrows()).fold(Complex::zero(), |a, i| a + m[(i, i)])
+}
+
+/// Largest entry of |U†U - I|, which is zero for a unitary `m`.
+pub fn unitarity_deviation(m: &DMatrix<Complex<f64>>) -> f64 {
+    let product = adjoint(m) * m.clone();
+    let mut deviation = 0f64;
+    for r in 0..product.nrows() {
+        for c in 0..product.ncols() {
+            let expected = if r == c { 1. } else { 0. };
+            deviation = deviation.max((product[(r, c)] - Complex::new(expected, 0.)).norm());
+        }
+    }
+    deviation
+}
+
+pub fn is_unitary(m: &DMatrix<Complex<f64>>, tolerance: f64) -> bool {
+    m.nrows() == m.ncols() && unitarity_deviation(m) <= tolerance
+}
+
+/// Eigenvalues of a Hermitian matrix in ascending order.
+pub fn hermitian_eigenvalues(m: &DMatrix<Complex<f64>>) -> Vec<f64> {
+    let (values, _) = embedded_eigen(m);
+    // The real embedding has every eigenvalue twice.
+    values.into_iter().step(2).collect()
+}
+
+/// Applies `f` to the eigenvalues of a Hermitian matrix, e.g. `f64::sqrt` for
+/// the square root of a positive semidefinite one.
+pub fn hermitian_map<F: Fn(f64) -> f64>(m: &DMatrix<Complex<f64>>, f: F) -> DMatrix<Complex<f64>> {
+    let n = m.nrows();
+    let (values, vectors) = embedded_eigen(m);
+    let dim = 2 * n;
+    let values = values.into_iter().map(f).collect::<Vec<_>>();
+    // Embedding is an algebra homomorphism, so the top left and bottom left
+    // blocks of f(embedding) are the real and imaginary parts of f(m).
+    let mut result = DMatrix::new_zeros(n, n);
+    for r in 0..n {
+        for c in 0..n {
+            let mut re = 0.;
+            let mut im = 0.;
+            for k in 0..dim {
+                re += vectors[r * dim + k] * values[k] * vectors[c * dim + k];
+                im += vectors[(r + n) * dim + k] * values[k] * vectors[c * dim + k];
+            }
+            result[(r, c)] = Complex::new(re, im);
+        }
+    }
+    result
+}
+
+/// Eigendecomposition of the real symmetric matrix [[A, -B], [B, A]] for m = A + iB.
+///
+/// Returns the eigenvalues sorted ascending and the row-major matrix of the
+/// corresponding eigenvectors as columns.
+fn embedded_eigen(m: &DMatrix<Complex<f64>>) -> (Vec<f64>, Vec<f64>) {
+    assert_eq!(m.nrows(), m.ncols());
+    let n = m.nrows();
+    let dim = 2 * n;
+    let mut a = vec![0.; dim * dim];
+    for r in 0..n {
+        for c in 0..n {
+            let e = m[(r, c)];
+            a[r * dim + c] = e.re;
+            a[(r + n) * dim + c + n] = e.re;
+            a[r * dim + c + n] = -e.im;
+            a[(r + n) * dim + c] = e.im;
+        }
+    }
+    let (values, vectors) = jacobi(a, dim);
+    let mut order = (0..dim).collect::<Vec<_>>();
+    order.sort_by(|&x, &y| values[x].partial_cmp(&values[y]).unwrap());
+    let mut sorted = vec![0.; dim * dim];
+    for r in 0..dim {
+        for (c, &o) in order.iter().enumerate() {
+            sorted[r * dim + c] = vectors[r * dim + o];
+        }
+    }
+    (order.into_iter().map(|o| values[o]).collect(), sorted)
+}
+
+/// Cyclic Jacobi eigenvalue algorithm for a row-major real symmetric matrix.
+fn jacobi(mut a: Vec<f64>, n: usize) -> (Vec<f64>, Vec<f64>) {
+    let mut v = vec![0.; n * n];
+    for i in 0..n {
+        v[i * n + i] = 1.;
+    }
+    for _ in 0..100 {
+        let mut off = 0.;
+        for p in 0..n {
+            for q in (p + 1)..n {
+                off += a[p * n + q] * a[p * n + q];
+            }
+        }
+        if off < 1e-24 {
+            break;
+        }
+        for p in 0..n {
+            for q in (p + 1)..n {
+                let apq = a[p * n + q];
+                if apq.abs() < 1e-300 {
+                    continue;
+                }
+                let theta = (a[q * n + q] - a[p * n + p]) / (2. * apq);
+                let t = theta.signum() / (theta.abs() + (theta * theta + 1.).sqrt());
+                let c = 1. / (t * t + 1.).sqrt();
+                let s = t * c;
+                for k in 0..n {
+                    let (akp, akq) = (a[k * n + p], a[k * n + q]);
+                    a[k * n + p] = c * akp - s * akq;
+                    a[k * n + q] = s * akp + c * akq;
+                }
+                for k in 0..n {
+                    let (apk, aqk) = (a[p * n + k], a[q * n + k]);
+                    a[p * n + k] = c * apk - s * aqk;
+                    a[q * n + k] = s * apk + c * aqk;
+                }
+                for k in 0..n {
+                    let (vkp, vkq) = (v[k * n + p], v[k * n + q]);
+                    v[k * n + p] = c * vkp - s * vkq;
+                    v[k * n + q] = s * vkp + c * vkq;
+                }
+            }
+        }
+    }
+    ((0..n).map(|i| a[i * n + i]).collect(), v)
+}
+
+#[test]
+fn hermitian_eigenvalues_test() {
+    use {not, pauli_y, hadamard};
+    let close = |a: &[f64], b: &[f64]| a.iter().zip(b).all(|(a, b)| (a - b).abs() < 0.000001);
+    assert!(close(&[-1., 1.], &hermitian_eigenvalues(&not())));
+    assert!(close(&[-1., 1.], &hermitian_eigenvalues(&pauli_y())));
+    assert!(close(&[-1., 1.], &hermitian_eigenvalues(&hadamard())));
+    let mut m = DMatrix::new_zeros(3, 3);
+    m[(0, 0)] = Complex::new(2., 0.);
+    m[(1, 1)] = Complex::new(3., 0.);
+    m[(2, 2)] = Complex::new(3., 0.);
+    m[(1, 2)] = Complex::new(0., 1.);
+    m[(2, 1)] = Complex::new(0., -1.);
+    assert!(close(&[2., 2., 4.], &hermitian_eigenvalues(&m)));
+    let root = hermitian_map(&m, f64::sqrt);
+    let square = root.clone() * root;
+    assert!(square.as_vector().iter().zip(m.as_vector()).all(|(a, b)| (a - b).norm() < 0.000001));
+}
+
+#[test]
+fn is_unitary_test() {
+    use {hadamard, toffoli, u3};
+    assert!(is_unitary(&hadamard(), 0.000001));
+    assert!(is_unitary(&toffoli(0, 1, 2, 3), 0.000001));
+    assert!(is_unitary(&u3(0.3, 1.2, -2.), 0.000001));
+    let m = hadamard() * Complex::new(1.1, 0.);
+    assert!((unitarity_deviation(&m) - 0.21).abs() < 0.000001);
+    assert!(!is_unitary(&m, 0.000001));
+    assert!(!is_unitary(&DMatrix::new_zeros(2, 1), 0.000001));
+}
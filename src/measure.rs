@@ -0,0 +1,107 @@
+use rand::Rng;
+
+use std::collections::BTreeMap;
+
+/// Counts of measured bitstrings. Keys are one bit per qubit like `basis_n`
+/// yields them, so iterating the histogram follows the basis order.
+pub type Histogram = BTreeMap<Vec<usize>, usize>;
+
+/// Bits of the basis state `index` at the `targets` qubits of an `qubits` qubit
+/// register, packed with the first target as the most significant bit.
+pub fn outcome_of(index: usize, qubits: usize, targets: &[usize]) -> usize {
+    targets.iter().fold(0, |acc, t| (acc << 1) | ((index >> (qubits - 1 - t)) & 1))
+}
+
+/// Unpacks an outcome of `outcome_of` into one bit per target, like `basis_n` does.
+pub fn outcome_bits(outcome: usize, targets: usize) -> Vec<usize> {
+    (0..targets).rev().map(|x| (outcome >> x) & 1).collect()
+}
+
+/// Picks an index with probability proportional to its weight.
+pub fn choose<R: Rng>(weights: &[f64], rng: &mut R) -> usize {
+    let total: f64 = weights.iter().sum();
+    let mut r = rng.gen::<f64>() * total;
+    let mut last = 0;
+    for (i, &w) in weights.iter().enumerate() {
+        if w > 0. {
+            if r < w {
+                return i;
+            }
+            r -= w;
+            last = i;
+        }
+    }
+    // Only reachable through rounding, so the last possible outcome is the right one.
+    last
+}
+
+/// Measures the `targets` qubits of an `qubits` qubit register `shots` times.
+///
+/// `probabilities` are those of the full basis, as `StateVector::probabilities`
+/// returns them. Each shot starts from the same state, like rerunning a circuit
+/// on hardware does.
+pub fn sample<R: Rng>(probabilities: &[f64], qubits: usize, targets: &[usize], shots: usize, rng: &mut R) -> Histogram {
+    let mut marginal = vec![0.; 1 << targets.len()];
+    for (i, p) in probabilities.iter().enumerate() {
+        marginal[outcome_of(i, qubits, targets)] += *p;
+    }
+    let mut cumulative = Vec::with_capacity(marginal.len());
+    let mut total = 0.;
+    for p in &marginal {
+        total += *p;
+        cumulative.push(total);
+    }
+    let mut counts = vec![0; marginal.len()];
+    for _ in 0..shots {
+        let r = rng.gen::<f64>() * total;
+        let mut i = match cumulative.binary_search_by(|c| c.partial_cmp(&r).unwrap()) {
+            Ok(i) => i + 1,
+            Err(i) => i,
+        };
+        if i >= marginal.len() {
+            i = marginal.len() - 1;
+        }
+        while marginal[i] <= 0. && i > 0 {
+            i -= 1;
+        }
+        counts[i] += 1;
+    }
+    counts.into_iter()
+        .enumerate()
+        .filter(|&(_, c)| c > 0)
+        .map(|(o, c)| (outcome_bits(o, targets.len()), c))
+        .collect()
+}
+
+#[test]
+fn outcome_test() {
+    assert_eq!(0b10, outcome_of(0b100, 3, &[0, 2]));
+    assert_eq!(0b01, outcome_of(0b100, 3, &[2, 0]));
+    assert_eq!(0b11, outcome_of(0b011, 3, &[1, 2]));
+    assert_eq!(vec![1, 0, 1], outcome_bits(0b101, 3));
+}
+
+#[test]
+fn choose_test() {
+    use rand::{StdRng, SeedableRng};
+    let mut rng = StdRng::from_seed(&[42][..]);
+    for _ in 0..100 {
+        assert_eq!(2, choose(&[0., 0., 1., 0.], &mut rng));
+        assert!(choose(&[0.5, 0., 0., 0.5], &mut rng) % 3 == 0);
+    }
+}
+
+#[test]
+fn sample_test() {
+    use rand::{StdRng, SeedableRng};
+    let mut rng = StdRng::from_seed(&[3][..]);
+    let h = sample(&[0.5, 0., 0., 0.5], 2, &[0, 1], 1000, &mut rng);
+    assert_eq!(vec![&vec![0, 0], &vec![1, 1]], h.keys().collect::<Vec<_>>());
+    assert_eq!(1000, h.values().sum::<usize>());
+    assert!(h[&vec![0, 0]] > 400 && h[&vec![1, 1]] > 400);
+
+    let h = sample(&[0., 0.25, 0., 0.75], 2, &[1], 100, &mut rng);
+    assert_eq!(Some(&100), h.get(&vec![1]));
+    let h = sample(&[0., 0.25, 0., 0.75], 2, &[0], 1000, &mut rng);
+    assert!(h[&vec![0]] > 150 && h[&vec![1]] > 650);
+}
@@ -0,0 +1,115 @@
+use nalgebra::DMatrix;
+use num::{Complex, Zero};
+
+use linalg::{trace, hermitian_eigenvalues, hermitian_map};
+use density::to_density;
+use adjoint;
+
+/// <a|b> for two state vectors, conjugating `a`.
+pub fn overlap(a: &DMatrix<Complex<f64>>, b: &DMatrix<Complex<f64>>) -> Complex<f64> {
+    assert_eq!(1, a.ncols());
+    assert_eq!(1, b.ncols());
+    assert_eq!(a.nrows(), b.nrows());
+    a.as_vector().iter()
+        .zip(b.as_vector())
+        .fold(Complex::zero(), |s, (a, b)| s + a.conj() * b)
+}
+
+/// Fidelity between two states, each either a state vector or a density matrix.
+///
+/// This is |<a|b>|² for two pure states, <a|b|a> for a pure and a mixed state
+/// and (Tr sqrt(sqrt(a) b sqrt(a)))² for two mixed states.
+pub fn fidelity(a: &DMatrix<Complex<f64>>, b: &DMatrix<Complex<f64>>) -> f64 {
+    match (a.ncols() == 1, b.ncols() == 1) {
+        (true, true) => overlap(a, b).norm_sqr(),
+        (true, false) => (adjoint(a) * b.clone() * a.clone())[(0, 0)].re,
+        (false, true) => fidelity(b, a),
+        (false, false) => {
+            let root = hermitian_map(a, |x| x.max(0.).sqrt());
+            let inner = root.clone() * b.clone() * root;
+            let inner = hermitian_map(&inner, |x| x.max(0.).sqrt());
+            trace(&inner).re.powi(2)
+        }
+    }
+}
+
+/// Trace distance ½ Tr|a - b| between two states, each either a state vector or a density matrix.
+pub fn trace_distance(a: &DMatrix<Complex<f64>>, b: &DMatrix<Complex<f64>>) -> f64 {
+    let diff = to_density(a) - to_density(b);
+    hermitian_eigenvalues(&diff).iter().map(|x| x.abs()).sum::<f64>() / 2.
+}
+
+/// Tolerance the crate uses when it checks gates and compares circuits.
+pub const TOLERANCE: f64 = 0.000001;
+
+/// Whether `a` and `b` have the same shape and each pair of elements is
+/// within `tolerance`, without allowing for any global phase.
+pub fn approx_eq(a: &DMatrix<Complex<f64>>, b: &DMatrix<Complex<f64>>, tolerance: f64) -> bool {
+    a.nrows() == b.nrows() && a.ncols() == b.ncols() &&
+        a.as_vector().iter().zip(b.as_vector()).all(|(a, b)| (a - b).norm() <= tolerance)
+}
+
+/// Whether `a` and `b` are equal up to a global phase, within `tolerance` per element.
+///
+/// Works for state vectors as well as for unitaries.
+pub fn eq_up_to_phase(a: &DMatrix<Complex<f64>>, b: &DMatrix<Complex<f64>>, tolerance: f64) -> bool {
+    if a.nrows() != b.nrows() || a.ncols() != b.ncols() {
+        return false;
+    }
+    let a = a.as_vector();
+    let b = b.as_vector();
+    if a.is_empty() {
+        return true;
+    }
+    // Comparing at the largest element keeps the phase estimate accurate.
+    let pivot = (0..a.len()).fold(0, |p, i| if a[i].norm() > a[p].norm() { i } else { p });
+    if a[pivot].norm() <= tolerance {
+        return b.iter().all(|x| x.norm() <= tolerance);
+    }
+    if b[pivot].norm() <= tolerance {
+        return false;
+    }
+    let phase = b[pivot] / a[pivot];
+    let phase = phase / phase.norm();
+    a.iter().zip(b).all(|(a, b)| (a * phase - b).norm() <= tolerance)
+}
+
+#[test]
+fn overlap_test() {
+    use num::One;
+    let y = Complex::new(1. / 2f64.sqrt(), 0.);
+    let plus = DMatrix::from_column_vector(2, 1, &[y, y]);
+    let plus_i = DMatrix::from_column_vector(2, 1, &[y, y * Complex::new(0., 1.)]);
+    let zero = DMatrix::from_column_vector(2, 1, &[Complex::one(), Complex::zero()]);
+    assert!((overlap(&plus_i, &plus_i) - Complex::one()).norm() < 0.000001);
+    assert!((overlap(&plus_i, &plus) - Complex::new(0.5, -0.5)).norm() < 0.000001);
+    assert!((fidelity(&plus, &zero) - 0.5).abs() < 0.000001);
+    // Without the conjugate |+i> would look orthogonal to itself.
+    assert!((fidelity(&plus_i, &plus_i) - 1.).abs() < 0.000001);
+}
+
+#[test]
+fn mixed_fidelity_test() {
+    use num::One;
+    use density::DensityMatrix;
+    use noise::depolarizing;
+    let zero = DMatrix::from_column_vector(2, 1, &[Complex::one(), Complex::zero()]);
+    let mut rho = DensityMatrix::new(1);
+    rho.apply_channel(&depolarizing(0.5), &[0]);
+    let rho = rho.mat();
+    assert!((fidelity(&zero, &rho) - 0.75).abs() < 0.000001);
+    assert!((fidelity(&to_density(&zero), &rho) - 0.75).abs() < 0.000001);
+    assert!((fidelity(&rho, &rho) - 1.).abs() < 0.000001);
+    assert!((trace_distance(&zero, &rho) - 0.25).abs() < 0.000001);
+    assert!(trace_distance(&rho, &rho).abs() < 0.000001);
+}
+
+#[test]
+fn eq_up_to_phase_test() {
+    use {hadamard, rz, phase, not};
+    let i = Complex::new(0., 1.);
+    assert!(eq_up_to_phase(&hadamard(), &(hadamard() * i), 0.000001));
+    assert!(eq_up_to_phase(&rz(0.4), &phase(0.4), 0.000001));
+    assert!(!eq_up_to_phase(&rz(0.4), &phase(0.5), 0.000001));
+    assert!(!eq_up_to_phase(&not(), &hadamard(), 0.000001));
+}
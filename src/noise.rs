@@ -0,0 +1,68 @@
+use nalgebra::{DMatrix, Eye};
+use num::{Complex, Zero};
+
+use {not, pauli_y, pauli_z};
+
+/// Replaces the state with the maximally mixed state with probability `p`.
+pub fn depolarizing(p: f64) -> Vec<DMatrix<Complex<f64>>> {
+    let a = Complex::new((1. - 3. * p / 4.).sqrt(), 0.);
+    let b = Complex::new((p / 4.).sqrt(), 0.);
+    vec![DMatrix::new_identity(2) * a, not() * b, pauli_y() * b, pauli_z() * b]
+}
+
+/// Decays |1> to |0> with probability `gamma`.
+pub fn amplitude_damping(gamma: f64) -> Vec<DMatrix<Complex<f64>>> {
+    use num::Complex as C;
+    vec![
+        DMatrix::from_column_vector(2, 2,
+            &[C::new(1., 0.), C::zero(),
+              C::zero(), C::new((1. - gamma).sqrt(), 0.)]),
+        DMatrix::from_column_vector(2, 2,
+            &[C::zero(), C::zero(),
+              C::new(gamma.sqrt(), 0.), C::zero()])]
+}
+
+/// Loses phase information without energy loss, scaling the coherences by sqrt(1 - `lambda`).
+pub fn phase_damping(lambda: f64) -> Vec<DMatrix<Complex<f64>>> {
+    use num::Complex as C;
+    vec![
+        DMatrix::from_column_vector(2, 2,
+            &[C::new(1., 0.), C::zero(),
+              C::zero(), C::new((1. - lambda).sqrt(), 0.)]),
+        DMatrix::from_column_vector(2, 2,
+            &[C::zero(), C::zero(),
+              C::zero(), C::new(lambda.sqrt(), 0.)])]
+}
+
+/// Applies X with probability `p`.
+pub fn bit_flip(p: f64) -> Vec<DMatrix<Complex<f64>>> {
+    pauli_channel(not(), p)
+}
+
+/// Applies Z with probability `p`.
+pub fn phase_flip(p: f64) -> Vec<DMatrix<Complex<f64>>> {
+    pauli_channel(pauli_z(), p)
+}
+
+/// Applies Y with probability `p`.
+pub fn bit_phase_flip(p: f64) -> Vec<DMatrix<Complex<f64>>> {
+    pauli_channel(pauli_y(), p)
+}
+
+fn pauli_channel(pauli: DMatrix<Complex<f64>>, p: f64) -> Vec<DMatrix<Complex<f64>>> {
+    vec![DMatrix::new_identity(2) * Complex::new((1. - p).sqrt(), 0.), pauli * Complex::new(p.sqrt(), 0.)]
+}
+
+#[test]
+fn kraus_completeness_test() {
+    use adjoint;
+    let channels = vec![depolarizing(0.3), amplitude_damping(0.3), phase_damping(0.3),
+                        bit_flip(0.3), phase_flip(0.3), bit_phase_flip(0.3)];
+    for kraus in channels {
+        let sum = kraus.iter()
+            .map(|k| adjoint(k) * k.clone())
+            .fold(DMatrix::new_zeros(2, 2), |a, b| a + b);
+        let id: DMatrix<Complex<f64>> = DMatrix::new_identity(2);
+        assert!(sum.as_vector().iter().zip(id.as_vector()).all(|(a, b)| (a - b).norm() < 0.000001));
+    }
+}
@@ -0,0 +1,434 @@
+use num::Complex;
+use rand::Rng;
+
+use circuit::gate::{Kind, quarter_turns};
+
+/// Stabilizer state in the tableau form of Aaronson and Gottesman's CHP
+/// simulator.
+///
+/// Clifford gates and measurements take time polynomial in the number of
+/// qubits, so circuits of hundreds of qubits are fine where a `StateVector`
+/// would need 2^n amplitudes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Tableau {
+    qubits: usize,
+    // Rows 0..n are destabilizers, n..2n stabilizers and 2n is scratch space.
+    x: Vec<bool>,
+    z: Vec<bool>,
+    r: Vec<bool>,
+}
+
+impl Tableau {
+    /// All qubits in |0⟩.
+    pub fn new(qubits: usize) -> Tableau {
+        let rows = 2 * qubits + 1;
+        let mut tableau = Tableau {
+            qubits: qubits,
+            x: vec![false; rows * qubits],
+            z: vec![false; rows * qubits],
+            r: vec![false; rows],
+        };
+        for i in 0..qubits {
+            tableau.x[i * qubits + i] = true;
+            tableau.z[(i + qubits) * qubits + i] = true;
+        }
+        tableau
+    }
+
+    pub fn qubits(&self) -> usize {
+        self.qubits
+    }
+
+    pub fn h(&mut self, a: usize) {
+        for i in 0..2 * self.qubits {
+            let j = i * self.qubits + a;
+            self.r[i] ^= self.x[j] && self.z[j];
+            let t = self.x[j];
+            self.x[j] = self.z[j];
+            self.z[j] = t;
+        }
+    }
+
+    pub fn s(&mut self, a: usize) {
+        for i in 0..2 * self.qubits {
+            let j = i * self.qubits + a;
+            self.r[i] ^= self.x[j] && self.z[j];
+            self.z[j] ^= self.x[j];
+        }
+    }
+
+    pub fn s_dagger(&mut self, a: usize) {
+        for i in 0..2 * self.qubits {
+            let j = i * self.qubits + a;
+            self.r[i] ^= self.x[j] && !self.z[j];
+            self.z[j] ^= self.x[j];
+        }
+    }
+
+    pub fn x(&mut self, a: usize) {
+        for i in 0..2 * self.qubits {
+            self.r[i] ^= self.z[i * self.qubits + a];
+        }
+    }
+
+    pub fn y(&mut self, a: usize) {
+        for i in 0..2 * self.qubits {
+            let j = i * self.qubits + a;
+            self.r[i] ^= self.x[j] ^ self.z[j];
+        }
+    }
+
+    pub fn z(&mut self, a: usize) {
+        for i in 0..2 * self.qubits {
+            self.r[i] ^= self.x[i * self.qubits + a];
+        }
+    }
+
+    pub fn cnot(&mut self, control: usize, target: usize) {
+        for i in 0..2 * self.qubits {
+            let (c, t) = (i * self.qubits + control, i * self.qubits + target);
+            self.r[i] ^= self.x[c] && self.z[t] && !(self.x[t] ^ self.z[c]);
+            self.x[t] ^= self.x[c];
+            self.z[c] ^= self.z[t];
+        }
+    }
+
+    pub fn cz(&mut self, a: usize, b: usize) {
+        self.h(b);
+        self.cnot(a, b);
+        self.h(b);
+    }
+
+    pub fn swap(&mut self, a: usize, b: usize) {
+        for i in 0..2 * self.qubits {
+            self.x.swap(i * self.qubits + a, i * self.qubits + b);
+            self.z.swap(i * self.qubits + a, i * self.qubits + b);
+        }
+    }
+
+    /// Applies a gate to the `qubits` its ports act on, in port order.
+    ///
+    /// `Input` prepares its state on a qubit that is still |0⟩ and `Output`
+    /// does nothing. Returns `false` without touching the tableau for gates
+    /// that aren't Clifford gates.
+    pub fn apply(&mut self, kind: &Kind, qubits: &[usize]) -> bool {
+        // Only a quick filter, the match below decides what is supported.
+        if !kind.is_clifford() {
+            return false;
+        }
+        match *kind {
+            Kind::Input { theta, phi } => {
+                let q = qubits[0];
+                match (quarter_turns(theta), quarter_turns(phi)) {
+                    (Some(0), _) => {}
+                    (Some(2), _) => self.x(q),
+                    (Some(t), Some(p)) => {
+                        self.h(q);
+                        if t == 3 {
+                            self.z(q);
+                        }
+                        self.rz(q, p);
+                    }
+                    _ => return false,
+                }
+            }
+            Kind::Output => {}
+            Kind::Not => self.x(qubits[0]),
+            Kind::PauliY => self.y(qubits[0]),
+            Kind::PauliZ => self.z(qubits[0]),
+            Kind::Hadamard => self.h(qubits[0]),
+            Kind::S => self.s(qubits[0]),
+            Kind::SDagger => self.s_dagger(qubits[0]),
+            Kind::Sx => {
+                self.h(qubits[0]);
+                self.s(qubits[0]);
+                self.h(qubits[0]);
+            }
+            Kind::Rz(theta) | Kind::Phase(theta) => match quarter_turns(theta) {
+                Some(t) => self.rz(qubits[0], t),
+                None => return false,
+            },
+            Kind::Rx(theta) => match quarter_turns(theta) {
+                Some(t) => self.rx(qubits[0], t),
+                None => return false,
+            },
+            Kind::Ry(theta) => match quarter_turns(theta) {
+                Some(t) => self.ry(qubits[0], t),
+                None => return false,
+            },
+            Kind::U3(theta, phi, lambda) => match (quarter_turns(theta), quarter_turns(phi), quarter_turns(lambda)) {
+                (Some(t), Some(p), Some(l)) => {
+                    self.rz(qubits[0], l);
+                    self.ry(qubits[0], t);
+                    self.rz(qubits[0], p);
+                }
+                _ => return false,
+            },
+            Kind::ControlNot => self.cnot(qubits[0], qubits[1]),
+            Kind::Swap => self.swap(qubits[0], qubits[1]),
+            Kind::ISwap => {
+                self.s(qubits[0]);
+                self.s(qubits[1]);
+                self.cz(qubits[0], qubits[1]);
+                self.swap(qubits[0], qubits[1]);
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    // Rotations by `turns` quarter turns, up to global phase.
+
+    fn rz(&mut self, a: usize, turns: usize) {
+        for _ in 0..turns {
+            self.s(a);
+        }
+    }
+
+    fn rx(&mut self, a: usize, turns: usize) {
+        self.h(a);
+        self.rz(a, turns);
+        self.h(a);
+    }
+
+    fn ry(&mut self, a: usize, turns: usize) {
+        self.s_dagger(a);
+        self.rx(a, turns);
+        self.s(a);
+    }
+
+    /// Measures `qubit` in the computational basis, collapsing the state.
+    pub fn measure<R: Rng>(&mut self, qubit: usize, rng: &mut R) -> usize {
+        self.collapse(qubit, || rng.gen())
+    }
+
+    /// Probability of measuring 1 on `qubit`, which is always 0, 1/2 or 1.
+    pub fn probability(&self, qubit: usize) -> f64 {
+        let n = self.qubits;
+        if (n..2 * n).any(|p| self.x[p * n + qubit]) {
+            0.5
+        } else {
+            self.clone().collapse(qubit, || unreachable!()) as f64
+        }
+    }
+
+    /// Probabilities of the basis states, ordered like `basis_n` yields them.
+    ///
+    /// This takes time exponential in the number of qubits, so it is only
+    /// useful for small registers.
+    pub fn probabilities(&self) -> Vec<f64> {
+        let mut result = vec![0.; 1 << self.qubits];
+        self.branch(0, 0, 1., &mut result);
+        result
+    }
+
+    fn branch(&self, qubit: usize, index: usize, p: f64, result: &mut [f64]) {
+        if qubit == self.qubits {
+            result[index] = p;
+        } else if self.probability(qubit) == 0.5 {
+            for &outcome in &[false, true] {
+                let mut t = self.clone();
+                t.collapse(qubit, || outcome);
+                t.branch(qubit + 1, (index << 1) | outcome as usize, p / 2., result);
+            }
+        } else {
+            let mut t = self.clone();
+            let outcome = t.collapse(qubit, || unreachable!());
+            t.branch(qubit + 1, (index << 1) | outcome, p, result);
+        }
+    }
+
+    /// Amplitudes of the state, ordered like `basis_n` yields them and up to
+    /// a global phase. Like `probabilities` this is exponential in the number
+    /// of qubits.
+    pub fn amplitudes(&self) -> Vec<Complex<f64>> {
+        let n = self.qubits;
+        // Projecting a basis state in the support onto the stabilized
+        // subspace gives the state.
+        let start = self.probabilities().iter().position(|&p| p > 0.).unwrap();
+        let mut state = vec![Complex::new(0., 0.); 1 << n];
+        state[start] = Complex::new(1., 0.);
+        for row in n..2 * n {
+            let mut next = state.clone();
+            for (b, a) in state.iter().enumerate() {
+                let mut a = if self.r[row] { -a } else { *a };
+                let mut flipped = b;
+                for j in 0..n {
+                    let bit = (b >> (n - 1 - j)) & 1 == 1;
+                    let (x, z) = (self.x[row * n + j], self.z[row * n + j]);
+                    if z && bit {
+                        a = -a;
+                    }
+                    if x && z {
+                        a = a * Complex::new(0., 1.);
+                    }
+                    if x {
+                        flipped ^= 1 << (n - 1 - j);
+                    }
+                }
+                next[flipped] = next[flipped] + a;
+            }
+            state = next;
+        }
+        let norm = state.iter().map(|a| a.norm_sqr()).sum::<f64>().sqrt();
+        state.into_iter().map(|a| a / norm).collect()
+    }
+
+    /// Measures `qubit`, taking the outcome from `random` if it isn't determined.
+    fn collapse<F: FnOnce() -> bool>(&mut self, qubit: usize, random: F) -> usize {
+        let n = self.qubits;
+        match (n..2 * n).find(|&p| self.x[p * n + qubit]) {
+            Some(p) => {
+                for i in 0..2 * n {
+                    if i != p && self.x[i * n + qubit] {
+                        self.rowsum(i, p);
+                    }
+                }
+                self.copy_row(p, p - n);
+                for j in 0..n {
+                    self.x[p * n + j] = false;
+                    self.z[p * n + j] = false;
+                }
+                self.z[p * n + qubit] = true;
+                self.r[p] = random();
+                self.r[p] as usize
+            }
+            None => {
+                let scratch = 2 * n;
+                for j in 0..n {
+                    self.x[scratch * n + j] = false;
+                    self.z[scratch * n + j] = false;
+                }
+                self.r[scratch] = false;
+                for i in 0..n {
+                    if self.x[i * n + qubit] {
+                        self.rowsum(scratch, i + n);
+                    }
+                }
+                self.r[scratch] as usize
+            }
+        }
+    }
+
+    fn copy_row(&mut self, from: usize, to: usize) {
+        let n = self.qubits;
+        for j in 0..n {
+            self.x[to * n + j] = self.x[from * n + j];
+            self.z[to * n + j] = self.z[from * n + j];
+        }
+        self.r[to] = self.r[from];
+    }
+
+    /// Multiplies row `h` by row `i`, keeping track of the sign.
+    fn rowsum(&mut self, h: usize, i: usize) {
+        let n = self.qubits;
+        let mut phase = 2 * (self.r[h] as i32 + self.r[i] as i32);
+        for j in 0..n {
+            let (x1, z1) = (self.x[i * n + j] as i32, self.z[i * n + j] as i32);
+            let (x2, z2) = (self.x[h * n + j] as i32, self.z[h * n + j] as i32);
+            phase += match (x1, z1) {
+                (0, 0) => 0,
+                (1, 1) => z2 - x2,
+                (1, 0) => z2 * (2 * x2 - 1),
+                _ => x2 * (1 - 2 * z2),
+            };
+        }
+        self.r[h] = ((phase % 4) + 4) % 4 == 2;
+        for j in 0..n {
+            self.x[h * n + j] ^= self.x[i * n + j];
+            self.z[h * n + j] ^= self.z[i * n + j];
+        }
+    }
+}
+
+#[test]
+fn bell_state_test() {
+    use rand::{StdRng, SeedableRng};
+    let mut rng = StdRng::from_seed(&[5][..]);
+    let mut seen = [false; 2];
+    for _ in 0..20 {
+        let mut t = Tableau::new(2);
+        t.h(0);
+        t.cnot(0, 1);
+        assert_eq!(0.5, t.probability(0));
+        let a = t.measure(0, &mut rng);
+        assert_eq!(a as f64, t.probability(1));
+        assert_eq!(a, t.measure(1, &mut rng));
+        assert_eq!(a, t.measure(0, &mut rng));
+        seen[a] = true;
+    }
+    assert_eq!([true, true], seen);
+}
+
+#[test]
+fn ghz_test() {
+    use rand::{StdRng, SeedableRng};
+    let mut rng = StdRng::from_seed(&[6][..]);
+    let n = 300;
+    let mut t = Tableau::new(n);
+    t.h(0);
+    for i in 1..n {
+        t.cnot(i - 1, i);
+    }
+    t.x(n - 1);
+    let first = t.measure(0, &mut rng);
+    for i in 1..n - 1 {
+        assert_eq!(first, t.measure(i, &mut rng));
+    }
+    assert_eq!(1 - first, t.measure(n - 1, &mut rng));
+}
+
+#[test]
+fn matches_state_vector_test() {
+    use rand::{StdRng, SeedableRng};
+    use circuit::gate::Kind;
+    use state::StateVector;
+    use {not, pauli_y, pauli_z, hadamard, s, s_dagger, sqrt_not, rx, ry, rz, u3, control_not, swap, iswap};
+    use std::f64::consts::PI;
+    let gates = vec![
+        (Kind::Hadamard, hadamard(), vec![0]),
+        (Kind::S, s(), vec![0]),
+        (Kind::ControlNot, control_not(0, 1, 2), vec![0, 2]),
+        (Kind::PauliY, pauli_y(), vec![1]),
+        (Kind::Hadamard, hadamard(), vec![1]),
+        (Kind::ISwap, iswap(0, 1, 2), vec![1, 2]),
+        (Kind::Rx(PI / 2.), rx(PI / 2.), vec![2]),
+        (Kind::Swap, swap(0, 1, 2), vec![0, 1]),
+        (Kind::SDagger, s_dagger(), vec![0]),
+        (Kind::Sx, sqrt_not(), vec![1]),
+        (Kind::Ry(-PI / 2.), ry(-PI / 2.), vec![1]),
+        (Kind::ControlNot, control_not(0, 1, 2), vec![2, 1]),
+        (Kind::U3(PI / 2., PI, -PI / 2.), u3(PI / 2., PI, -PI / 2.), vec![0]),
+        (Kind::Rz(3. * PI / 2.), rz(3. * PI / 2.), vec![2]),
+        (Kind::PauliZ, pauli_z(), vec![1]),
+        (Kind::Not, not(), vec![2]),
+    ];
+    let mut tableau = Tableau::new(3);
+    let mut state = StateVector::new(3);
+    for (kind, matrix, qubits) in gates {
+        assert!(tableau.apply(&kind, &qubits));
+        state.apply(&matrix, &qubits);
+        let overlap = tableau.amplitudes().iter().zip(state.amplitudes())
+            .fold(Complex::new(0., 0.), |acc, (a, b)| acc + a.conj() * b);
+        assert!((overlap.norm() - 1.).abs() < 0.000001);
+        assert!(tableau.probabilities().iter().zip(state.probabilities()).all(|(a, b)| (a - b).abs() < 0.000001));
+        // Measuring the qubits one by one and collapsing the state vector to
+        // the tableau's outcomes compares every conditional probability.
+        let mut rng = StdRng::from_seed(&[qubits.len() * 7 + 1][..]);
+        let mut t = tableau.clone();
+        let mut amplitudes = state.amplitudes().to_vec();
+        for q in 0..3 {
+            let one = amplitudes.iter().enumerate()
+                .filter(|&(i, _)| (i >> (2 - q)) & 1 == 1)
+                .map(|(_, a)| a.norm_sqr())
+                .sum::<f64>();
+            assert!((one - t.probability(q)).abs() < 0.000001);
+            let outcome = t.measure(q, &mut rng);
+            let p = if outcome == 1 { one } else { 1. - one };
+            for (i, a) in amplitudes.iter_mut().enumerate() {
+                *a = if (i >> (2 - q)) & 1 == outcome { *a / p.sqrt() } else { Complex::new(0., 0.) };
+            }
+        }
+    }
+    assert!(!tableau.apply(&Kind::T, &[0]));
+}
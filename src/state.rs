@@ -0,0 +1,240 @@
+use nalgebra::DMatrix;
+use num::{Complex, One, Zero};
+use rand::Rng;
+
+use std::ops::Index;
+
+use measure::{outcome_of, outcome_bits, choose, sample, Histogram};
+
+/// Pure state of a register stored as its 2^n amplitudes.
+///
+/// Qubit 0 is the most significant bit of a basis index, which is the same
+/// ordering `basis_n`, `apply_to_qubit` and `kronecker_product` use. Gates are
+/// applied to the amplitudes in place, so no 2^n x 2^n matrix is ever built.
+#[derive(Clone, Debug, PartialEq)]
+pub struct StateVector {
+    qubits: usize,
+    amplitudes: Vec<Complex<f64>>,
+}
+
+impl StateVector {
+    /// Register of `qubits` qubits in the state |0...0>.
+    pub fn new(qubits: usize) -> StateVector {
+        let mut amplitudes = vec![Complex::zero(); 1 << qubits];
+        amplitudes[0] = Complex::one();
+        StateVector {
+            qubits: qubits,
+            amplitudes: amplitudes,
+        }
+    }
+
+    pub fn from_amplitudes(amplitudes: Vec<Complex<f64>>) -> StateVector {
+        let len = amplitudes.len();
+        assert!(len.is_power_of_two(), "State vector length {} is not a power of two", len);
+        StateVector {
+            qubits: len.trailing_zeros() as usize,
+            amplitudes: amplitudes,
+        }
+    }
+
+    /// Takes a column vector in the layout used by `Qubit` and `kronecker_product`.
+    pub fn from_mat(state: &DMatrix<Complex<f64>>) -> StateVector {
+        assert_eq!(1, state.ncols());
+        StateVector::from_amplitudes(state.as_vector().to_vec())
+    }
+
+    pub fn mat(&self) -> DMatrix<Complex<f64>> {
+        DMatrix::from_column_vector(self.amplitudes.len(), 1, &self.amplitudes)
+    }
+
+    pub fn qubits(&self) -> usize {
+        self.qubits
+    }
+
+    pub fn amplitudes(&self) -> &[Complex<f64>] {
+        &self.amplitudes
+    }
+
+    /// Probability of every basis state, in the order of `basis_n`.
+    pub fn probabilities(&self) -> Vec<f64> {
+        self.amplitudes.iter().map(|a| a.norm_sqr()).collect()
+    }
+
+    /// Measures `qubit` in the computational basis and collapses the state.
+    pub fn measure<R: Rng>(&mut self, qubit: usize, rng: &mut R) -> usize {
+        self.measure_qubits(&[qubit], rng)[0]
+    }
+
+    /// Measures `targets` in the computational basis and collapses the state.
+    ///
+    /// Returns one bit per target in the same order as `targets`.
+    pub fn measure_qubits<R: Rng>(&mut self, targets: &[usize], rng: &mut R) -> Vec<usize> {
+        let mut probabilities = vec![0.; 1 << targets.len()];
+        for (i, a) in self.amplitudes.iter().enumerate() {
+            probabilities[outcome_of(i, self.qubits, targets)] += a.norm_sqr();
+        }
+        let outcome = choose(&probabilities, rng);
+        let norm = Complex::new(probabilities[outcome].sqrt(), 0.);
+        for (i, a) in self.amplitudes.iter_mut().enumerate() {
+            if outcome_of(i, self.qubits, targets) == outcome {
+                *a = *a / norm;
+            } else {
+                *a = Complex::zero();
+            }
+        }
+        outcome_bits(outcome, targets.len())
+    }
+
+    /// Measures every qubit `shots` times without disturbing the state.
+    pub fn sample<R: Rng>(&self, shots: usize, rng: &mut R) -> Histogram {
+        let targets: Vec<_> = (0..self.qubits).collect();
+        self.sample_qubits(&targets, shots, rng)
+    }
+
+    /// Measures the `targets` qubits `shots` times without disturbing the state.
+    pub fn sample_qubits<R: Rng>(&self, targets: &[usize], shots: usize, rng: &mut R) -> Histogram {
+        sample(&self.probabilities(), self.qubits, targets, shots, rng)
+    }
+
+    /// Applies `gate` to the `targets` qubits of the register.
+    ///
+    /// The first target is the most significant qubit of the gate, so
+    /// `control_not(0, 1, 2)` applied to `[a, b]` uses `a` as control.
+    pub fn apply(&mut self, gate: &DMatrix<Complex<f64>>, targets: &[usize]) {
+        let dim = 1 << targets.len();
+        assert_eq!(dim, gate.nrows());
+        assert_eq!(dim, gate.ncols());
+        for (i, &t) in targets.iter().enumerate() {
+            assert!(t < self.qubits, "Qubit {} is outside of the {} qubit register", t, self.qubits);
+            assert!(!targets[..i].contains(&t), "Qubit {} is targeted twice", t);
+        }
+        if targets.len() == 1 {
+            self.apply_single(gate, targets[0]);
+        } else {
+            self.apply_multi(gate, targets);
+        }
+    }
+
+    fn apply_single(&mut self, gate: &DMatrix<Complex<f64>>, target: usize) {
+        let (a, b, c, d) = (gate[(0, 0)], gate[(0, 1)], gate[(1, 0)], gate[(1, 1)]);
+        let bit = 1 << (self.qubits - 1 - target);
+        for i in 0..self.amplitudes.len() {
+            if i & bit == 0 {
+                let x = self.amplitudes[i];
+                let y = self.amplitudes[i | bit];
+                self.amplitudes[i] = a * x + b * y;
+                self.amplitudes[i | bit] = c * x + d * y;
+            }
+        }
+    }
+
+    fn apply_multi(&mut self, gate: &DMatrix<Complex<f64>>, targets: &[usize]) {
+        let dim = 1 << targets.len();
+        let bits: Vec<usize> = targets.iter()
+            .map(|t| 1 << (self.qubits - 1 - t))
+            .collect();
+        let mut sorted = bits.clone();
+        sorted.sort();
+        // Offsets of every local basis state of the gate inside the register.
+        let offsets: Vec<usize> = (0..dim)
+            .map(|l| bits.iter()
+                .enumerate()
+                .filter(|&(j, _)| (l >> (targets.len() - 1 - j)) & 1 == 1)
+                .fold(0, |acc, (_, b)| acc | b))
+            .collect();
+        let mut old = vec![Complex::zero(); dim];
+        for r in 0..(self.amplitudes.len() >> targets.len()) {
+            let mut base = r;
+            for &b in &sorted {
+                base = ((base & !(b - 1)) << 1) | (base & (b - 1));
+            }
+            for l in 0..dim {
+                old[l] = self.amplitudes[base | offsets[l]];
+            }
+            for row in 0..dim {
+                let mut sum = Complex::zero();
+                for col in 0..dim {
+                    sum = sum + gate[(row, col)] * old[col];
+                }
+                self.amplitudes[base | offsets[row]] = sum;
+            }
+        }
+    }
+}
+
+impl Index<usize> for StateVector {
+    type Output = Complex<f64>;
+
+    fn index(&self, n: usize) -> &Complex<f64> {
+        &self.amplitudes[n]
+    }
+}
+
+#[test]
+fn state_vector_measure_test() {
+    use {hadamard, control_not};
+    use rand::{StdRng, SeedableRng};
+    let mut rng = StdRng::from_seed(&[1, 2, 3][..]);
+    for _ in 0..20 {
+        let mut s = StateVector::new(3);
+        s.apply(&hadamard(), &[0]);
+        s.apply(&control_not(0, 1, 2), &[0, 1]);
+        s.apply(&hadamard(), &[2]);
+        let a = s.measure(1, &mut rng);
+        assert!((s.probabilities().iter().sum::<f64>() - 1.).abs() < 0.000001);
+        let bits = s.measure_qubits(&[2, 0], &mut rng);
+        assert_eq!(a, bits[1]);
+        let mut collapsed = vec![0.; 8];
+        collapsed[a << 2 | a << 1 | bits[0]] = 1.;
+        assert!(s.probabilities().iter().zip(&collapsed).all(|(a, b)| (a - b).abs() < 0.000001));
+    }
+
+    let mut first = StdRng::from_seed(&[7][..]);
+    let mut second = StdRng::from_seed(&[7][..]);
+    let mut s = StateVector::new(4);
+    for q in 0..4 {
+        s.apply(&hadamard(), &[q]);
+    }
+    let mut t = s.clone();
+    assert_eq!(s.measure_qubits(&[0, 1, 2, 3], &mut first), t.measure_qubits(&[0, 1, 2, 3], &mut second));
+}
+
+#[test]
+fn state_vector_single_qubit_test() {
+    use {Qubit, kronecker_product, apply_to_qubit, not, hadamard, pauli_y};
+    use metrics::{approx_eq, TOLERANCE};
+    use num::Complex as C;
+    let q1 = Qubit::new(C::new(1. / 2f64.sqrt(), 0.), C::new(0., 1. / 2f64.sqrt()));
+    let q2 = Qubit::new(C::new(0.6, 0.), C::new(0.8, 0.));
+    let q3 = Qubit::new(C::new(3. / 10f64.sqrt(), 0.), C::new(1. / 10f64.sqrt(), 0.));
+    let q123 = kronecker_product(&[q1.mat().clone(), q2.mat().clone(), q3.mat().clone()]);
+    for (i, gate) in [not(), hadamard(), pauli_y()].iter().enumerate() {
+        for target in 0..3 {
+            let mut s = StateVector::from_mat(&q123);
+            s.apply(gate, &[target]);
+            let r = apply_to_qubit(gate.clone(), target, 3) * q123.clone();
+            assert!(approx_eq(&r, &s.mat(), TOLERANCE), "gate {} on qubit {}", i, target);
+        }
+    }
+}
+
+#[test]
+fn state_vector_control_not_test() {
+    use {control_not, hadamard};
+    use metrics::{approx_eq, TOLERANCE};
+    let mut s = StateVector::new(3);
+    s.apply(&hadamard(), &[0]);
+    s.apply(&control_not(0, 1, 2), &[0, 2]);
+    let mut r = DMatrix::new_zeros(8, 1);
+    r[(0, 0)] = Complex::new(1. / 2f64.sqrt(), 0.);
+    r[(5, 0)] = Complex::new(1. / 2f64.sqrt(), 0.);
+    assert!(approx_eq(&r, &s.mat(), TOLERANCE));
+
+    let mut s = StateVector::new(3);
+    s.apply(&hadamard(), &[0]);
+    s.apply(&control_not(0, 1, 3), &[0, 1, 2]);
+    let mut reversed = StateVector::new(3);
+    reversed.apply(&hadamard(), &[0]);
+    reversed.apply(&control_not(1, 0, 2), &[1, 0]);
+    assert!(approx_eq(&s.mat(), &reversed.mat(), TOLERANCE));
+}